@@ -0,0 +1,266 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    modifiers::{Modifier, ModifierKind, Selector},
+    stats::{Save, Stat},
+};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum Condition {
+    Blinded,
+    Clumsy,
+    Dazzled,
+    Deafened,
+    Drained,
+    Enfeebled,
+    Fatigued,
+    Frightened,
+    Grabbed,
+    OffGuard,
+    Prone,
+    Quickened,
+    Restrained,
+    Sickened,
+    Slowed,
+    Stunned,
+    Stupefied,
+    Unconscious,
+}
+
+impl Condition {
+    pub const ALL: &[Condition] = &[
+        Condition::Blinded,
+        Condition::Clumsy,
+        Condition::Dazzled,
+        Condition::Deafened,
+        Condition::Drained,
+        Condition::Enfeebled,
+        Condition::Fatigued,
+        Condition::Frightened,
+        Condition::Grabbed,
+        Condition::OffGuard,
+        Condition::Prone,
+        Condition::Quickened,
+        Condition::Restrained,
+        Condition::Sickened,
+        Condition::Slowed,
+        Condition::Stunned,
+        Condition::Stupefied,
+        Condition::Unconscious,
+    ];
+
+    pub fn name(&self) -> String {
+        match self {
+            Condition::OffGuard => "Off-guard".to_string(),
+            _ => format!("{self:?}"),
+        }
+    }
+
+    /// Whether this condition has a value, e.g. frightened 2.
+    pub fn valued(&self) -> bool {
+        matches!(
+            self,
+            Condition::Clumsy
+                | Condition::Drained
+                | Condition::Enfeebled
+                | Condition::Frightened
+                | Condition::Sickened
+                | Condition::Slowed
+                | Condition::Stunned
+                | Condition::Stupefied
+        )
+    }
+
+    pub fn format(&self, value: i64) -> String {
+        if self.valued() {
+            format!("{} {value}", self.name())
+        } else {
+            self.name()
+        }
+    }
+
+    /// Penalties this condition applies to checks and DCs at a value.
+    fn penalties(&self, value: i64) -> Vec<(ModifierKind, i64, Selector)> {
+        use ModifierKind::{Circumstance, Status};
+
+        match self {
+            Condition::Frightened | Condition::Sickened => {
+                vec![(Status, value, Selector::All)]
+            }
+            Condition::Clumsy => {
+                vec![(Status, value, Selector::Stat(Stat::Dexterity))]
+            }
+            Condition::Drained => {
+                vec![(Status, value, Selector::Stat(Stat::Constitution))]
+            }
+            Condition::Enfeebled => {
+                vec![(Status, value, Selector::Stat(Stat::Strength))]
+            }
+            Condition::Stupefied => {
+                [Stat::Intelligence, Stat::Wisdom, Stat::Charisma]
+                    .into_iter()
+                    .map(|stat| (Status, value, Selector::Stat(stat)))
+                    .collect()
+            }
+            Condition::OffGuard
+            | Condition::Prone
+            | Condition::Grabbed
+            | Condition::Restrained => vec![(Circumstance, 2, Selector::Ac)],
+            Condition::Fatigued => {
+                vec![(Status, 1, Selector::Ac), (Status, 1, Selector::Saves)]
+            }
+            Condition::Deafened => vec![(Status, 2, Selector::Perception)],
+            Condition::Blinded => vec![(Status, 4, Selector::Perception)],
+            Condition::Unconscious => vec![
+                (Status, 4, Selector::Ac),
+                (Status, 4, Selector::Perception),
+                (Status, 4, Selector::Save(Save::Reflex)),
+            ],
+            Condition::Dazzled
+            | Condition::Quickened
+            | Condition::Slowed
+            | Condition::Stunned => Vec::new(),
+        }
+    }
+}
+
+/// Conditions affecting the character, with their values. Conditions
+/// without a value have a value of 1 while active.
+#[derive(
+    Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct Conditions(BTreeMap<Condition, i64>);
+
+impl Conditions {
+    /// Active conditions and their values, in alphabetical order.
+    pub fn active(&self) -> Vec<(Condition, i64)> {
+        self.0.iter().map(|(&c, &v)| (c, v)).collect()
+    }
+
+    pub fn inactive(&self) -> Vec<Condition> {
+        Condition::ALL
+            .iter()
+            .copied()
+            .filter(|c| !self.0.contains_key(c))
+            .collect()
+    }
+
+    /// Add a condition, or increase its value if it has one.
+    pub fn increase(&mut self, condition: Condition) {
+        let value = self.0.entry(condition).or_default();
+        if condition.valued() || *value == 0 {
+            *value += 1;
+        }
+    }
+
+    /// Decrease the value of a condition, removing it at 0.
+    pub fn decrease(&mut self, condition: Condition) {
+        if let Some(value) = self.0.get_mut(&condition) {
+            *value -= 1;
+            if *value <= 0 {
+                self.0.remove(&condition);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, condition: Condition) {
+        self.0.remove(&condition);
+    }
+
+    /// Reduce the conditions which wear off at the end of each turn.
+    pub fn end_turn(&mut self) {
+        self.decrease(Condition::Frightened);
+    }
+
+    /// Penalties from the active conditions, as modifiers to be stacked with
+    /// those from other effects.
+    pub fn modifiers(&self) -> Vec<Modifier> {
+        self.0
+            .iter()
+            .flat_map(|(condition, &value)| {
+                let source = condition.format(value);
+                condition.penalties(value).into_iter().map(
+                    move |(kind, penalty, selector)| Modifier {
+                        source: source.clone(),
+                        kind,
+                        value: -penalty,
+                        selector,
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SheetState;
+
+    #[test]
+    fn test_conditions() {
+        let mut conditions = Conditions::default();
+        conditions.increase(Condition::Frightened);
+        conditions.increase(Condition::Frightened);
+        conditions.increase(Condition::Prone);
+        conditions.increase(Condition::Prone);
+        assert_eq!(
+            conditions.active(),
+            [(Condition::Frightened, 2), (Condition::Prone, 1)]
+        );
+        assert!(!conditions.inactive().contains(&Condition::Prone));
+
+        conditions.end_turn();
+        assert_eq!(
+            conditions.active(),
+            [(Condition::Frightened, 1), (Condition::Prone, 1)]
+        );
+        conditions.end_turn();
+        assert_eq!(conditions.active(), [(Condition::Prone, 1)]);
+
+        conditions.decrease(Condition::Prone);
+        assert!(conditions.active().is_empty());
+    }
+
+    #[test]
+    fn test_condition_penalties() {
+        let mut sheet = SheetState {
+            level: 1,
+            ..Default::default()
+        };
+        sheet.conditions.increase(Condition::Frightened);
+        sheet.conditions.increase(Condition::Frightened);
+        sheet.conditions.increase(Condition::Sickened);
+        sheet.conditions.increase(Condition::Clumsy);
+        sheet.conditions.increase(Condition::OffGuard);
+
+        // Frightened, sickened and clumsy are all status penalties, so only
+        // the worst applies.
+        let stealth = sheet.skills.lookup("Stealth").unwrap();
+        assert_eq!(stealth.modifier(&sheet), -2);
+        let roll = stealth.roll(&sheet);
+        assert_eq!(roll.format_breakdown(), "DEX +0, Frightened 2 -2");
+        assert_eq!(
+            roll.format_suppressed(),
+            "Suppressed: Clumsy 1: -1 status DEX-based, \
+             Sickened 1: -1 status all"
+        );
+        assert_eq!(sheet.ac.value(&sheet), 10 - 2 - 2);
+
+        sheet.conditions.remove(Condition::Frightened);
+        sheet.conditions.remove(Condition::Sickened);
+        let arcana = sheet.skills.lookup("Arcana").unwrap();
+        assert_eq!(arcana.modifier(&sheet), 0);
+        assert_eq!(sheet.saves.modifier(Save::Reflex, &sheet), -1);
+    }
+}
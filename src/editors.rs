@@ -2,24 +2,25 @@ use ratatui::{
     Frame,
     crossterm::event::{Event, KeyCode, KeyEventKind},
     layout::{Constraint, Rect},
-    text::ToLine,
-    widgets::{Block, Row, Table},
+    style::Stylize,
+    text::{Line, Span, ToLine},
+    widgets::{Block, Paragraph, Row, Table},
 };
 use tui_input::backend::crossterm::EventHandler;
 
 use crate::{
     Handler, SheetState,
     els::{self, BORDER, State, style_selected},
-    roll, spells, stats,
+    fs, modifiers, roll, spells, stats,
     view::{self, Dims, ElSimp, Scene},
 };
 
-struct MessageBox<T> {
+pub struct MessageBox<T> {
     layout: view::Layout<T>,
 }
 
 impl<T> MessageBox<T> {
-    fn new(message: impl ToString) -> MessageBox<T> {
+    pub fn new(message: impl ToString) -> MessageBox<T> {
         let mut layout = view::Layout::new();
         let message = message.to_string();
         let length = message.len();
@@ -40,6 +41,18 @@ impl<T> Scene<T> for MessageBox<T> {
     }
 }
 
+/// Write data to the named file in the data directory, returning a message
+/// box reporting where it was written or why it couldn't be.
+pub fn export(name: &str, data: impl AsRef<[u8]>) -> MessageBox<State> {
+    match fs::write_data(name, data) {
+        Ok(()) => MessageBox::new(format!(
+            "Exported to {}",
+            fs::data_path(name).display()
+        )),
+        Err(e) => MessageBox::new(format!("Failed to export {name}: {e}")),
+    }
+}
+
 #[derive(Default, Clone)]
 pub struct EditorState<T: Clone + Default> {
     shared_state: std::rc::Rc<std::cell::Cell<T>>,
@@ -68,50 +81,12 @@ impl<T: Clone + Default> EditorState<T> {
     }
 }
 
-struct CellDisplay<T: std::fmt::Display + Default + Clone> {
-    shared_state: EditorState<T>,
-    display_func: Box<dyn Fn(T) -> String>,
-}
-
-impl<T: std::fmt::Display + Default + Clone> CellDisplay<T> {
-    fn new(
-        shared_state: EditorState<T>,
-        display_func: &'static dyn Fn(T) -> String,
-    ) -> Self {
-        Self {
-            shared_state,
-            display_func: Box::new(display_func),
-        }
-    }
-    fn show(&self) -> String {
-        (self.display_func)(self.shared_state.get())
-    }
-}
-
-impl<T: std::fmt::Display + Default + Clone> ElSimp<State> for CellDisplay<T> {
-    fn dimensions(&self) -> Dims {
-        Dims::new(
-            Constraint::Length(self.show().len() as u16),
-            Constraint::Length(1),
-        )
-    }
-
-    fn render(
-        &self,
-        frame: &mut Frame,
-        area: Rect,
-        _state: &State,
-        selected: bool,
-    ) {
-        frame.render_widget(
-            els::style_selected(self.show().to_line().centered(), selected),
-            area,
-        );
-    }
-}
-
 type EditorSubmitHandler<T> = Box<dyn FnMut(T, &mut State)>;
 
+/// Submit handler which may reject the value, with a message saying why.
+type EditorCheckedHandler<T> =
+    Box<dyn FnMut(T, &mut State) -> Result<(), String>>;
+
 #[derive(Default)]
 pub struct StringDisplay {
     value: EditorState<String>,
@@ -175,14 +150,33 @@ impl ElSimp<SheetState> for StringEditor {
 
 pub struct StringEditorModal {
     layout: view::Layout<State>,
-    apply_to_state: EditorSubmitHandler<String>,
+    apply_to_state: EditorCheckedHandler<String>,
+    value: EditorState<String>,
+    input: tui_input::Input,
 }
 
 impl StringEditorModal {
     pub fn new(
         title: &str,
         initial_value: String,
-        handler: EditorSubmitHandler<String>,
+        mut handler: EditorSubmitHandler<String>,
+    ) -> Self {
+        Self::checked(
+            title,
+            initial_value,
+            Box::new(move |value, state| {
+                handler(value, state);
+                Ok(())
+            }),
+        )
+    }
+
+    /// Editor whose handler may reject the value, in which case the reason
+    /// is shown in a message box.
+    pub fn checked(
+        title: &str,
+        initial_value: String,
+        handler: EditorCheckedHandler<String>,
     ) -> Self {
         let input = tui_input::Input::new(initial_value.clone());
         let value = EditorState::new(initial_value);
@@ -240,8 +234,12 @@ impl Scene<State> for StringEditorModal {
     fn handle_key_press(&mut self, key: KeyCode, state: &mut State) -> Handler {
         match key {
             KeyCode::Enter => {
-                (self.apply_to_state)(self.value.get(), state);
-                Handler::Close
+                match (self.apply_to_state)(self.value.get(), state) {
+                    Ok(()) => Handler::Close,
+                    Err(message) => {
+                        Handler::Replace(Box::new(MessageBox::new(message)))
+                    }
+                }
             }
             KeyCode::Esc => Handler::Close,
             _ => Handler::Default,
@@ -249,31 +247,31 @@ impl Scene<State> for StringEditorModal {
     }
 }
 
-struct SkillProficiencyEditor {
-    skill: String,
+struct ProficiencyEditor {
+    title: String,
     state: EditorState<stats::Proficiency>,
 }
 
-impl SkillProficiencyEditor {
+impl ProficiencyEditor {
     fn new(
-        skill: &str,
+        title: &str,
         prof: stats::Proficiency,
     ) -> (EditorState<stats::Proficiency>, Self) {
         let state = EditorState::new(prof);
         (
             state.clone(),
             Self {
-                skill: skill.to_string(),
+                title: title.to_string(),
                 state,
             },
         )
     }
 }
 
-impl ElSimp<State> for SkillProficiencyEditor {
+impl ElSimp<State> for ProficiencyEditor {
     fn dimensions(&self) -> Dims {
         Dims::new(
-            Constraint::Min(self.skill.len() as u16 + BORDER),
+            Constraint::Min(self.title.len() as u16 + BORDER),
             Constraint::Length(stats::Proficiency::ALL.len() as u16 + BORDER),
         )
     }
@@ -295,41 +293,39 @@ impl ElSimp<State> for SkillProficiencyEditor {
     }
 }
 
-pub struct SkillModal {
+/// Modal to choose a proficiency rank, e.g. for a skill or save.
+pub struct ProficiencyModal {
     layout: view::Layout<State>,
-    skill: String,
     eds: EditorState<stats::Proficiency>,
+    handler: EditorSubmitHandler<stats::Proficiency>,
 }
 
-impl SkillModal {
-    pub fn new(skill: &str, state: &State) -> Self {
-        let prof = state
-            .skills
-            .lookup(skill)
-            .map(|s| s.proficiency)
-            .unwrap_or(stats::Proficiency::Untrained);
-        let (eds, editor) = SkillProficiencyEditor::new(skill, prof);
+impl ProficiencyModal {
+    pub fn new(
+        title: &str,
+        prof: stats::Proficiency,
+        handler: EditorSubmitHandler<stats::Proficiency>,
+    ) -> Self {
+        let (eds, editor) = ProficiencyEditor::new(title, prof);
         let (width, height) = editor.dimensions().into();
         let mut layout = view::Layout::new();
         layout.add_el(editor);
         Self {
-            layout: layout.modal(skill, view::Dims::new(width, height), false),
-            skill: skill.to_string(),
+            layout: layout.modal(title, view::Dims::new(width, height), false),
             eds,
+            handler,
         }
     }
 }
 
-impl Scene<State> for SkillModal {
+impl Scene<State> for ProficiencyModal {
     fn layout(&self) -> &view::Layout<State> {
         &self.layout
     }
 
     fn handle_key_press(&mut self, key: KeyCode, state: &mut State) -> Handler {
         if key == KeyCode::Enter {
-            if let Some(skill) = state.skills.lookup_mut(&self.skill) {
-                skill.proficiency = self.eds.get();
-            }
+            (self.handler)(self.eds.get(), state);
             return Handler::Close;
         }
 
@@ -432,45 +428,147 @@ impl Scene<State> for IntEditorModal {
     }
 }
 
+/// Displays the changes to a stat's score from each source while its manual
+/// adjustment is edited, and the resulting score.
+struct StatProvenance {
+    stat: stats::Stat,
+    adjustment: EditorState<i64>,
+}
+
+impl StatProvenance {
+    /// Build of the sheet with the adjustment being edited applied.
+    fn build(&self, state: &State) -> stats::Build {
+        let mut build = state.build.clone();
+        build.set_adjustment(self.stat, self.adjustment.get());
+        build
+    }
+
+    fn height(&self, state: &State) -> u16 {
+        // The manual adjustment and score are always shown.
+        let (_, changes) = self.build(state).provenance(self.stat, state.level);
+        changes
+            .iter()
+            .filter(|(label, _)| label != "Manual")
+            .count() as u16
+            + 2
+    }
+}
+
+impl ElSimp<State> for StatProvenance {
+    fn dimensions(&self) -> Dims {
+        Dims::new(Constraint::Min(24), Constraint::Fill(1))
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        state: &State,
+        _selected: bool,
+    ) {
+        let (score, changes) =
+            self.build(state).provenance(self.stat, state.level);
+        let mut lines = vec![Line::from(format!(
+            "Manual {:+} (< >)",
+            self.adjustment.get()
+        ))];
+        lines.extend(
+            changes.iter().filter(|(label, _)| label != "Manual").map(
+                |(label, amount)| {
+                    Line::from(format!("{label} {amount:+}")).dark_gray()
+                },
+            ),
+        );
+        lines.push(Line::from(format!(
+            "Score {score} ({})",
+            els::format_modifier(stats::Stat::modifier(score))
+        )));
+        frame.render_widget(Paragraph::new(lines), area);
+    }
+}
+
+/// Modal showing where a stat's score comes from. Left and right change the
+/// manual adjustment to the score.
 pub fn stat_modal(stat: stats::Stat, state: &State) -> Box<dyn Scene<State>> {
-    let score = state.stats.score(stat);
+    let adjustment = state.build.adjustment(stat);
     let mut modal = IntEditorModal::new(
         "",
-        score,
-        Box::new(move |score, state| state.stats.set_score(stat, score)),
+        adjustment,
+        Box::new(move |adjustment, state| {
+            state.build.set_adjustment(stat, adjustment);
+            state.rebuild_stats();
+        }),
     );
-    let modifier = CellDisplay::new(modal.eds.clone(), &|score| {
-        els::format_modifier(stats::Stat::modifier(score))
-    });
-    modal.layout.add_el(modifier);
+    let provenance = StatProvenance {
+        stat,
+        adjustment: modal.eds.clone(),
+    };
+    let height = provenance.height(state);
+    let mut layout = view::Layout::new();
+    layout.add_el(provenance);
     let dimensions = Dims::new(
-        Constraint::Length(2 + 2 + 2 + BORDER),
-        Constraint::Length(2 + BORDER),
+        Constraint::Length(32 + BORDER),
+        Constraint::Length(height + BORDER),
     );
-    modal.layout = modal.layout.modal(&stat.short(), dimensions, false);
+    modal.layout = layout.modal(&stat.short(), dimensions, false);
     Box::new(modal)
 }
 
+pub fn skill_modal(skill: &str, state: &State) -> Box<dyn Scene<State>> {
+    let prof = state
+        .skills
+        .lookup(skill)
+        .map(|s| s.proficiency)
+        .unwrap_or(stats::Proficiency::Untrained);
+    let name = skill.to_string();
+    Box::new(ProficiencyModal::new(
+        skill,
+        prof,
+        Box::new(move |prof, state| {
+            if let Some(skill) = state.skills.lookup_mut(&name) {
+                skill.proficiency = prof;
+            }
+        }),
+    ))
+}
+
 pub struct RollModal {
     outcome: roll::RollOutcome,
     layout: view::Layout<State>,
 }
 
 impl RollModal {
-    pub fn new(r: roll::Roll) -> Self {
-        let outcome = r.resolve();
+    pub fn new(r: roll::Roll, state: &State) -> Self {
+        // Variables which can't be found have already been reported by the
+        // editor, so are left as they were.
+        let r = state.bind_roll(r.clone()).unwrap_or(r);
+        Self::resolved(r.resolve(&state.dice))
+    }
+
+    /// Show a roll which has already been made. It is added to the history
+    /// when the modal is closed.
+    pub fn resolved(outcome: roll::RollOutcome) -> Self {
         let mut layout = view::Layout::new();
         let element = els::RollDisplay::new(&outcome);
-        let width = if let Constraint::Length(w) = element.dimensions().width()
-        {
-            w + BORDER
-        } else {
-            16
-        };
-        let width = Constraint::Length(width);
-        let height = Constraint::Length(2 + BORDER);
-        let dimensions = Dims::new(width, height);
+        let mut width =
+            if let Constraint::Length(w) = element.dimensions().width() {
+                w + BORDER
+            } else {
+                16
+            };
+        let mut height =
+            if let Constraint::Length(h) = element.dimensions().height() {
+                h + BORDER
+            } else {
+                2 + BORDER
+            };
         layout.add_el(element);
+        for hint in Self::hints(&outcome) {
+            width = width.max(hint.len() as u16 + BORDER);
+            height += 1;
+            layout.add_el(els::Text::new(hint));
+        }
+        let dimensions = Dims::length(width, height);
         Self {
             layout: layout.modal("Roll", dimensions, false),
             outcome,
@@ -478,6 +576,37 @@ impl RollModal {
     }
 }
 
+impl RollModal {
+    fn is_critical(outcome: &roll::RollOutcome) -> bool {
+        outcome.degree() == Some(roll::Degree::CriticalSuccess)
+    }
+
+    /// Expression to start the critical damage roll from: the macro the roll
+    /// was made with, or otherwise the roll itself.
+    fn critical_expression(
+        outcome: &roll::RollOutcome,
+        state: &State,
+    ) -> String {
+        outcome
+            .label()
+            .and_then(|label| state.macros.iter().find(|m| m.name == label))
+            .map(|m| m.expression.clone())
+            .unwrap_or_else(|| outcome.clone_roll().without_dc().format())
+    }
+
+    /// Describe the keys for follow up actions available for the outcome.
+    fn hints(outcome: &roll::RollOutcome) -> Vec<&'static str> {
+        let mut hints = Vec::new();
+        if Self::is_critical(outcome) {
+            hints.push("d: roll critical damage");
+        }
+        if outcome.has_damage_types() {
+            hints.push("t: apply target defenses");
+        }
+        hints
+    }
+}
+
 impl Scene<State> for RollModal {
     fn layout(&self) -> &view::Layout<State> {
         &self.layout
@@ -489,29 +618,88 @@ impl Scene<State> for RollModal {
                 state.rolls.push(self.outcome.clone());
                 Handler::Close
             }
+            KeyCode::Char('d') if Self::is_critical(&self.outcome) => {
+                state.rolls.push(self.outcome.clone());
+                let expression =
+                    Self::critical_expression(&self.outcome, state);
+                Handler::Replace(Box::new(RollEditorModal::critical(
+                    expression,
+                )))
+            }
+            KeyCode::Char('t') if self.outcome.has_damage_types() => {
+                let modal = DefensesModal::new(self.outcome.clone(), state);
+                Handler::Replace(Box::new(modal))
+            }
             _ => Handler::Default,
         }
     }
 }
 
-pub struct RollEditorModal {
+/// Displays the defenses being entered for the target of a damage roll, with
+/// a preview of the damage after applying them.
+struct DefensesDisplay {
+    value: EditorState<String>,
+    outcome: roll::RollOutcome,
+}
+
+impl ElSimp<State> for DefensesDisplay {
+    fn dimensions(&self) -> Dims {
+        Dims::new(Constraint::Min(16), Constraint::Length(2))
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        _state: &State,
+        _selected: bool,
+    ) {
+        let text = self.value.get();
+        let preview = match roll::Defenses::parse(&text) {
+            Ok(defenses) => {
+                let outcome = self.outcome.clone().with_defenses(defenses);
+                Line::from(format!(
+                    "= {}: {}",
+                    outcome.format_damage(),
+                    outcome.format_value()
+                ))
+                .dark_gray()
+            }
+            Err(message) => Line::from(message).red(),
+        };
+        let input = if text.is_empty() {
+            Line::from("e.g. resist fire 5, weak cold 3, immune poison")
+                .dark_gray()
+        } else {
+            text.to_line()
+        };
+        frame.render_widget(Paragraph::new(vec![input, preview]), area);
+    }
+}
+
+/// Modal to enter the immunities, resistances and weaknesses of the target of
+/// a damage roll, which are applied before recording the roll.
+pub struct DefensesModal {
     layout: view::Layout<State>,
     value: EditorState<String>,
     input: tui_input::Input,
+    outcome: roll::RollOutcome,
 }
 
-impl RollEditorModal {
-    pub fn new() -> Self {
-        let input = tui_input::Input::default();
-        let value = EditorState::new(String::new());
-        let el = StringDisplay {
+impl DefensesModal {
+    pub fn new(outcome: roll::RollOutcome, state: &State) -> Self {
+        let initial_value = state.target_defenses.clone();
+        let input = tui_input::Input::new(initial_value.clone());
+        let value = EditorState::new(initial_value);
+        let el = DefensesDisplay {
             value: value.clone(),
+            outcome: outcome.clone(),
         };
         let mut layout = view::Layout::new();
         layout.add_el(el);
         let layout = layout.modal(
-            "Roll",
-            Dims::new(Constraint::Min(24), Constraint::Length(1 + BORDER)),
+            "Target defenses",
+            Dims::new(Constraint::Min(48), Constraint::Length(2 + BORDER)),
             false,
         );
 
@@ -519,11 +707,12 @@ impl RollEditorModal {
             layout,
             value,
             input,
+            outcome,
         }
     }
 }
 
-impl Scene<State> for RollEditorModal {
+impl Scene<State> for DefensesModal {
     fn layout(&self) -> &view::Layout<State> {
         &self.layout
     }
@@ -554,18 +743,1261 @@ impl Scene<State> for RollEditorModal {
         }
     }
 
-    fn handle_key_press(
+    fn handle_key_press(&mut self, key: KeyCode, state: &mut State) -> Handler {
+        match key {
+            KeyCode::Enter => match roll::Defenses::parse(&self.value.get()) {
+                Ok(defenses) => {
+                    state.target_defenses = defenses.format();
+                    let outcome = self.outcome.clone().with_defenses(defenses);
+                    state.rolls.push(outcome);
+                    Handler::Close
+                }
+                // The reason is shown in the preview.
+                Err(_) => Handler::Consume,
+            },
+            KeyCode::Esc => {
+                state.rolls.push(self.outcome.clone());
+                Handler::Close
+            }
+            _ => Handler::Default,
+        }
+    }
+}
+
+/// Displays a modifier being entered, with how it was understood.
+struct ModifierDisplay {
+    value: EditorState<String>,
+}
+
+impl ElSimp<State> for ModifierDisplay {
+    fn dimensions(&self) -> Dims {
+        Dims::new(Constraint::Min(16), Constraint::Length(2))
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        state: &State,
+        _selected: bool,
+    ) {
+        let text = self.value.get();
+        let (input, preview) = if text.is_empty() {
+            (
+                Line::from("e.g. Heroism: +1 status all").dark_gray(),
+                Line::from("types: circumstance, status, item").dark_gray(),
+            )
+        } else {
+            let preview = match ModifierModal::parse(&text, state) {
+                Ok(modifier) => {
+                    Line::from(format!("= {}", modifier.format())).dark_gray()
+                }
+                Err(message) => Line::from(message).red(),
+            };
+            (text.to_line(), preview)
+        };
+        frame.render_widget(Paragraph::new(vec![input, preview]), area);
+    }
+}
+
+/// Modal to enter a bonus or penalty from an effect, written as
+/// source: value [type] selector.
+pub struct ModifierModal {
+    layout: view::Layout<State>,
+    value: EditorState<String>,
+    input: tui_input::Input,
+    handler: EditorSubmitHandler<modifiers::Modifier>,
+}
+
+impl ModifierModal {
+    pub fn new(
+        title: &str,
+        initial_value: String,
+        handler: EditorSubmitHandler<modifiers::Modifier>,
+    ) -> Self {
+        let input = tui_input::Input::new(initial_value.clone());
+        let value = EditorState::new(initial_value);
+        let el = ModifierDisplay {
+            value: value.clone(),
+        };
+        let mut layout = view::Layout::new();
+        layout.add_el(el);
+        let layout = layout.modal(
+            title,
+            Dims::new(Constraint::Min(48), Constraint::Length(2 + BORDER)),
+            false,
+        );
+
+        Self {
+            layout,
+            value,
+            input,
+            handler,
+        }
+    }
+
+    fn parse(text: &str, state: &State) -> Result<modifiers::Modifier, String> {
+        let modifier = modifiers::Modifier::parse(text)?;
+        modifier.check(&state.skills)?;
+        Ok(modifier)
+    }
+}
+
+impl Scene<State> for ModifierModal {
+    fn layout(&self) -> &view::Layout<State> {
+        &self.layout
+    }
+
+    fn handle(
         &mut self,
-        key: KeyCode,
-        _state: &mut State,
+        event: Event,
+        state: &mut State,
+        _selected: view::ElPos,
     ) -> Handler {
+        if let Event::Key(evt) = event
+            && evt.kind == KeyEventKind::Press
+        {
+            let result = self.handle_key_press(evt.code, state);
+            if !matches!(result, Handler::Default) {
+                return result;
+            }
+        }
+
+        match self.input.handle_event(&event) {
+            Some(changes) => {
+                if changes.value {
+                    self.value.set(self.input.value().to_string());
+                }
+                Handler::Consume
+            }
+            None => Handler::Default,
+        }
+    }
+
+    fn handle_key_press(&mut self, key: KeyCode, state: &mut State) -> Handler {
         match key {
-            KeyCode::Enter => match roll::Roll::parse(&self.value.get()) {
-                Some(roll) => Handler::Replace(Box::new(RollModal::new(roll))),
-                None => Handler::Replace(Box::new(MessageBox::new(
-                    "Failed to parse roll.",
-                ))),
-            },
+            KeyCode::Enter => {
+                match Self::parse(&self.value.get(), state) {
+                    Ok(modifier) => {
+                        (self.handler)(modifier, state);
+                        Handler::Close
+                    }
+                    // The reason is shown in the preview.
+                    Err(_) => Handler::Consume,
+                }
+            }
+            KeyCode::Esc => Handler::Close,
+            _ => Handler::Default,
+        }
+    }
+}
+
+/// Displays an amount of HP being entered, with the effect of applying it.
+struct HitPointsDisplay {
+    value: EditorState<String>,
+}
+
+impl HitPointsDisplay {
+    const HINT: &str = "Enter: damage, c: critical, h: heal, t: temp HP";
+}
+
+impl ElSimp<State> for HitPointsDisplay {
+    fn dimensions(&self) -> Dims {
+        Dims::new(
+            Constraint::Min(Self::HINT.len() as u16),
+            Constraint::Length(3),
+        )
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        state: &State,
+        _selected: bool,
+    ) {
+        let text = self.value.get();
+        let input = if text.is_empty() {
+            Line::from("Amount").dark_gray()
+        } else if text.trim().parse::<i64>().is_err() {
+            Line::from(vec![
+                Span::from(text),
+                Span::from(" (not a number)").red(),
+            ])
+        } else {
+            text.to_line()
+        };
+        let hp = &state.hp;
+        let mut current = format!("HP {}/{}", hp.current(state), hp.max(state));
+        if hp.temp > 0 {
+            current.push_str(&format!(", {} temp", hp.temp));
+        }
+        frame.render_widget(
+            Paragraph::new(vec![
+                input,
+                Line::from(current).dark_gray(),
+                Line::from(Self::HINT).dark_gray(),
+            ]),
+            area,
+        );
+    }
+}
+
+/// Modal to apply damage or healing to the character, or grant them
+/// temporary HP.
+pub struct HitPointsModal {
+    layout: view::Layout<State>,
+    value: EditorState<String>,
+    input: tui_input::Input,
+}
+
+impl HitPointsModal {
+    pub fn new() -> Self {
+        let value = EditorState::new(String::new());
+        let el = HitPointsDisplay {
+            value: value.clone(),
+        };
+        let width = el.dimensions().width();
+        let mut layout = view::Layout::new();
+        layout.add_el(el);
+        let layout = layout.modal(
+            "Hit Points",
+            Dims::new(width, Constraint::Length(3 + BORDER)),
+            false,
+        );
+
+        Self {
+            layout,
+            value,
+            input: tui_input::Input::default(),
+        }
+    }
+
+    /// Apply the amount entered to the sheet, if it is a number.
+    fn apply(&self, state: &mut State, effect: fn(&mut State, i64)) -> Handler {
+        match self.value.get().trim().parse::<i64>() {
+            Ok(amount) => {
+                effect(state, amount);
+                Handler::Close
+            }
+            Err(_) => Handler::Consume,
+        }
+    }
+}
+
+impl Scene<State> for HitPointsModal {
+    fn layout(&self) -> &view::Layout<State> {
+        &self.layout
+    }
+
+    fn handle(
+        &mut self,
+        event: Event,
+        state: &mut State,
+        _selected: view::ElPos,
+    ) -> Handler {
+        if let Event::Key(evt) = event
+            && evt.kind == KeyEventKind::Press
+        {
+            let result = self.handle_key_press(evt.code, state);
+            if !matches!(result, Handler::Default) {
+                return result;
+            }
+        }
+
+        match self.input.handle_event(&event) {
+            Some(changes) => {
+                if changes.value {
+                    self.value.set(self.input.value().to_string());
+                }
+                Handler::Consume
+            }
+            None => Handler::Default,
+        }
+    }
+
+    fn handle_key_press(&mut self, key: KeyCode, state: &mut State) -> Handler {
+        match key {
+            KeyCode::Enter => self
+                .apply(state, |state, amount| state.take_damage(amount, false)),
+            KeyCode::Char('c') => self
+                .apply(state, |state, amount| state.take_damage(amount, true)),
+            KeyCode::Char('h') => {
+                self.apply(state, |state, amount| state.heal(amount))
+            }
+            KeyCode::Char('t') => {
+                self.apply(state, |state, amount| state.hp.gain_temp(amount))
+            }
+            KeyCode::Esc => Handler::Close,
+            _ => Handler::Default,
+        }
+    }
+}
+
+/// Modal showing the result of a recovery check, which has already been
+/// applied to the dying condition.
+pub struct RecoveryCheckModal {
+    layout: view::Layout<State>,
+}
+
+impl RecoveryCheckModal {
+    /// Show a recovery check which took dying from the before value to its
+    /// current value.
+    pub fn resolved(
+        outcome: &roll::RollOutcome,
+        before: i64,
+        state: &State,
+    ) -> Self {
+        let element = els::RollDisplay::new(outcome);
+        let mut lines =
+            vec![format!("Dying {before} -> {}", state.death.dying)];
+        if before > 0 && state.death.dying == 0 {
+            lines.push(format!("Wounded {}", state.death.wounded));
+        }
+        let mut width = match element.dimensions().width() {
+            Constraint::Length(w) => w,
+            _ => 16,
+        };
+        let mut height = match element.dimensions().height() {
+            Constraint::Length(h) => h,
+            _ => 2,
+        };
+        let mut layout = view::Layout::new();
+        layout.add_el(element);
+        for line in lines {
+            width = width.max(line.len() as u16);
+            height += 1;
+            layout.add_el(els::Text::new(line));
+        }
+        if state.death.is_dead() {
+            height += 1;
+            layout.add_el(els::DeathWarning);
+        }
+
+        Self {
+            layout: layout.modal(
+                "Recovery check",
+                Dims::length(width.max(24) + BORDER, height + BORDER),
+                false,
+            ),
+        }
+    }
+}
+
+impl Scene<State> for RecoveryCheckModal {
+    fn layout(&self) -> &view::Layout<State> {
+        &self.layout
+    }
+
+    fn handle_key_press(
+        &mut self,
+        key: KeyCode,
+        _state: &mut State,
+    ) -> Handler {
+        match key {
+            KeyCode::Enter | KeyCode::Esc => Handler::Close,
+            _ => Handler::Default,
+        }
+    }
+}
+
+/// Scrolling list of options to choose from, with the selected option
+/// highlighted.
+struct OptionList {
+    selected: EditorState<usize>,
+    rows: fn(&State) -> Vec<Row<'static>>,
+    widths: Vec<Constraint>,
+}
+
+impl OptionList {
+    /// Render rows of options, scrolled to keep the selected row in view.
+    fn render_rows(
+        frame: &mut Frame,
+        area: Rect,
+        rows: Vec<Row<'static>>,
+        widths: &[Constraint],
+        selected: usize,
+    ) {
+        let skip = (selected + 1).saturating_sub(area.height as usize);
+        let rows = rows
+            .into_iter()
+            .enumerate()
+            .skip(skip)
+            .map(|(i, row)| style_selected(row, i == selected));
+        frame.render_widget(Table::new(rows, widths.to_vec()), area);
+    }
+
+    /// Move the selection up or down a list of count options, or None if
+    /// the key isn't for navigation.
+    fn navigate(
+        selected: &EditorState<usize>,
+        key: KeyCode,
+        count: usize,
+    ) -> Option<Handler> {
+        match view::Navigation::from_key_code(key)? {
+            view::Navigation::Up => selected.update(|i| i.saturating_sub(1)),
+            view::Navigation::Down => {
+                selected.update(|i| (i + 1).min(count.saturating_sub(1)))
+            }
+            _ => return None,
+        }
+        Some(Handler::Consume)
+    }
+}
+
+impl ElSimp<State> for OptionList {
+    fn dimensions(&self) -> Dims {
+        Dims::new(Constraint::Fill(1), Constraint::Fill(1))
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        state: &State,
+        _selected: bool,
+    ) {
+        Self::render_rows(
+            frame,
+            area,
+            (self.rows)(state),
+            &self.widths,
+            self.selected.get(),
+        );
+    }
+}
+
+/// Modal to choose one of a list of options, e.g. the roll used for
+/// initiative or a condition to add.
+pub struct OptionModal {
+    layout: view::Layout<State>,
+    selected: EditorState<usize>,
+    rows: fn(&State) -> Vec<Row<'static>>,
+    handler: fn(usize, &mut State) -> Handler,
+}
+
+impl OptionModal {
+    /// Maximum number of options shown at once.
+    const MAX_ROWS: u16 = 12;
+
+    fn new(
+        title: &str,
+        width: u16,
+        widths: Vec<Constraint>,
+        rows: fn(&State) -> Vec<Row<'static>>,
+        handler: fn(usize, &mut State) -> Handler,
+        state: &State,
+    ) -> Self {
+        let selected = EditorState::new(0);
+        let height = (rows(state).len() as u16).clamp(1, Self::MAX_ROWS);
+        let el = OptionList {
+            selected: selected.clone(),
+            rows,
+            widths,
+        };
+        let mut layout = view::Layout::new();
+        layout.add_el(el);
+        Self {
+            layout: layout.modal(
+                title,
+                Dims::new(
+                    Constraint::Length(width + BORDER),
+                    Constraint::Length(height + BORDER),
+                ),
+                false,
+            ),
+            selected,
+            rows,
+            handler,
+        }
+    }
+
+    /// Modal to choose the roll used for initiative, either Perception or a
+    /// skill. The result is recorded as the current initiative.
+    pub fn initiative(state: &State) -> Self {
+        Self::new(
+            "Initiative",
+            24,
+            vec![Constraint::Fill(1), Constraint::Length(3)],
+            |state| {
+                initiative_options(state)
+                    .into_iter()
+                    .map(|option| {
+                        let modifier = initiative_modifier(state, &option);
+                        Row::new([option, els::format_modifier(modifier)])
+                    })
+                    .collect()
+            },
+            |i, state| {
+                let option = &initiative_options(state)[i];
+                let outcome =
+                    initiative_roll(state, option).resolve(&state.dice);
+                state.initiative = Some(outcome.total() as i64);
+                Handler::Replace(Box::new(RollModal::resolved(outcome)))
+            },
+            state,
+        )
+    }
+
+    /// Modal to choose a condition to add to the character.
+    pub fn condition(state: &State) -> Self {
+        Self::new(
+            "Add condition",
+            16,
+            vec![Constraint::Fill(1)],
+            |state| {
+                state
+                    .conditions
+                    .inactive()
+                    .iter()
+                    .map(|condition| Row::new([condition.name()]))
+                    .collect()
+            },
+            |i, state| {
+                let condition = state.conditions.inactive()[i];
+                state.conditions.increase(condition);
+                Handler::Close
+            },
+            state,
+        )
+    }
+}
+
+impl Scene<State> for OptionModal {
+    fn layout(&self) -> &view::Layout<State> {
+        &self.layout
+    }
+
+    fn handle_key_press(&mut self, key: KeyCode, state: &mut State) -> Handler {
+        let count = (self.rows)(state).len();
+        match key {
+            KeyCode::Enter => {
+                let selected = self.selected.get();
+                if selected < count {
+                    (self.handler)(selected, state)
+                } else {
+                    Handler::Close
+                }
+            }
+            KeyCode::Esc => Handler::Close,
+            _ => OptionList::navigate(&self.selected, key, count)
+                .unwrap_or(Handler::Default),
+        }
+    }
+}
+
+/// Names of the rolls which can be used for initiative, Perception first.
+fn initiative_options(state: &State) -> Vec<String> {
+    std::iter::once(stats::Perception::NAME.to_string())
+        .chain(state.skills.0.iter().map(|s| s.name.clone()))
+        .collect()
+}
+
+fn initiative_modifier(state: &State, option: &str) -> i64 {
+    match state.skills.lookup(option) {
+        Some(skill) => skill.modifier(state),
+        None => state.perception.modifier(state),
+    }
+}
+
+fn initiative_roll(state: &State, option: &str) -> roll::Roll {
+    let roll = match state.skills.lookup(option) {
+        Some(skill) => skill.roll(state),
+        None => state.perception.roll(state),
+    };
+    roll.labelled(format!("Initiative ({option})"))
+}
+
+/// Choice which can be made when levelling up.
+#[derive(Clone)]
+enum LevelUpOption {
+    Boost(stats::Stat),
+    Feat(usize),
+    Skill(String),
+}
+
+/// Summary of a level up in progress and the list of choices to make, with
+/// the selected choice highlighted.
+struct LevelUpDisplay {
+    draft: EditorState<stats::LevelUp>,
+    selected: EditorState<usize>,
+}
+
+impl LevelUpDisplay {
+    /// Lines of summary shown above the choices.
+    const SUMMARY_LINES: u16 = 4;
+
+    fn summary(&self, state: &State) -> Vec<Line<'static>> {
+        let draft = self.draft.get();
+        let grants = draft.grants();
+        let (before, after) = draft.hp(state);
+        let status = match draft.validate(state) {
+            Ok(()) => Line::from("Ready, a: apply, Esc: cancel").green(),
+            Err(e) => Line::from(e).red(),
+        };
+        vec![
+            Line::from(format!("Level {} -> {}", state.level, draft.level)),
+            Line::from(format!("Max HP {before} -> {after}")),
+            Line::from(format!(
+                "{} boosts, {} skill increases, {} feats",
+                grants.ability_boosts,
+                grants.skill_increases,
+                grants.feats.len()
+            )),
+            status,
+        ]
+    }
+
+    fn row(
+        &self,
+        state: &State,
+        draft: &stats::LevelUp,
+        option: &LevelUpOption,
+    ) -> Row<'static> {
+        let mark = |chosen| if chosen { "[x]" } else { "[ ]" };
+        match option {
+            LevelUpOption::Boost(stat) => {
+                let before = state.stats.score(*stat);
+                let after = draft.build(state).stats(draft.level).score(*stat);
+                Row::new([
+                    mark(draft.boosts(*stat)).to_string(),
+                    stat.short(),
+                    format!("{before} -> {after}"),
+                ])
+            }
+            LevelUpOption::Feat(i) => {
+                let feat = &draft.feats[*i];
+                Row::new([
+                    String::new(),
+                    format!("{:?} feat", feat.kind),
+                    feat.name.clone(),
+                ])
+            }
+            LevelUpOption::Skill(name) => {
+                let proficiency = state
+                    .skills
+                    .lookup(name)
+                    .map(|skill| skill.proficiency)
+                    .unwrap_or_default();
+                let next = proficiency.increase();
+                let row = Row::new([
+                    mark(draft.increases(name)).to_string(),
+                    name.clone(),
+                    format!("{proficiency:?} -> {next:?}"),
+                ]);
+                if next == proficiency
+                    || next > stats::proficiency_cap(draft.level)
+                {
+                    row.dim()
+                } else {
+                    row
+                }
+            }
+        }
+    }
+}
+
+impl ElSimp<State> for LevelUpDisplay {
+    fn dimensions(&self) -> Dims {
+        Dims::new(Constraint::Fill(1), Constraint::Fill(1))
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        state: &State,
+        _selected: bool,
+    ) {
+        let [summary, choices] = ratatui::layout::Layout::vertical([
+            Constraint::Length(Self::SUMMARY_LINES + 1),
+            Constraint::Fill(1),
+        ])
+        .areas(area);
+        frame.render_widget(Paragraph::new(self.summary(state)), summary);
+
+        let draft = self.draft.get();
+        let rows = LevelUpModal::options(state, &draft)
+            .iter()
+            .map(|option| self.row(state, &draft, option))
+            .collect();
+        OptionList::render_rows(
+            frame,
+            choices,
+            rows,
+            &[
+                Constraint::Length(3),
+                Constraint::Length(16),
+                Constraint::Fill(1),
+            ],
+            self.selected.get(),
+        );
+    }
+}
+
+/// Modal to advance the character to their next level, choosing ability
+/// boosts, skill increases and feats. The choices are only applied once
+/// they are all valid.
+pub struct LevelUpModal {
+    layout: view::Layout<State>,
+    draft: EditorState<stats::LevelUp>,
+    selected: EditorState<usize>,
+}
+
+impl LevelUpModal {
+    pub fn new(state: &State) -> Self {
+        let draft = EditorState::new(stats::LevelUp::new(state));
+        let selected = EditorState::new(0);
+        let el = LevelUpDisplay {
+            draft: draft.clone(),
+            selected: selected.clone(),
+        };
+        let mut layout = view::Layout::new();
+        layout.add_el(el);
+        Self {
+            layout: layout.modal(
+                "Level Up",
+                Dims::new(Constraint::Length(48), Constraint::Percentage(80)),
+                false,
+            ),
+            draft,
+            selected,
+        }
+    }
+
+    /// Choices available at the level being reached: boosts, then feats,
+    /// then skills.
+    fn options(state: &State, draft: &stats::LevelUp) -> Vec<LevelUpOption> {
+        let grants = draft.grants();
+        let mut options = Vec::new();
+        if grants.ability_boosts > 0 {
+            options.extend(
+                stats::Stat::STATS.iter().map(|&s| LevelUpOption::Boost(s)),
+            );
+        }
+        options.extend((0..draft.feats.len()).map(LevelUpOption::Feat));
+        if grants.skill_increases > 0 {
+            options.extend(
+                state
+                    .skills
+                    .0
+                    .iter()
+                    .map(|s| LevelUpOption::Skill(s.name.clone())),
+            );
+        }
+        options
+    }
+
+    fn select(&self, state: &State) -> Handler {
+        let draft = self.draft.get();
+        let options = Self::options(state, &draft);
+        let Some(option) = options.get(self.selected.get()) else {
+            return Handler::Consume;
+        };
+        match option {
+            LevelUpOption::Boost(stat) => {
+                self.draft.update(|mut d| {
+                    d.toggle_boost(*stat);
+                    d
+                });
+                Handler::Consume
+            }
+            LevelUpOption::Skill(name) => {
+                self.draft.update(|mut d| {
+                    d.toggle_skill_increase(name);
+                    d
+                });
+                Handler::Consume
+            }
+            &LevelUpOption::Feat(i) => {
+                let feat = &draft.feats[i];
+                let target = self.draft.clone();
+                Handler::Open(Box::new(StringEditorModal::new(
+                    &format!("{:?} feat", feat.kind),
+                    feat.name.clone(),
+                    Box::new(move |name, _| {
+                        target.update(|mut d| {
+                            d.feats[i].name = name;
+                            d
+                        })
+                    }),
+                )))
+            }
+        }
+    }
+}
+
+impl Scene<State> for LevelUpModal {
+    fn layout(&self) -> &view::Layout<State> {
+        &self.layout
+    }
+
+    fn handle_key_press(&mut self, key: KeyCode, state: &mut State) -> Handler {
+        match key {
+            KeyCode::Enter | KeyCode::Char(' ') => self.select(state),
+            KeyCode::Char('a') => match self.draft.get().apply(state) {
+                Ok(()) => Handler::Close,
+                Err(_) => Handler::Consume,
+            },
+            KeyCode::Esc => Handler::Close,
+            _ => {
+                let count = Self::options(state, &self.draft.get()).len();
+                OptionList::navigate(&self.selected, key, count)
+                    .unwrap_or(Handler::Default)
+            }
+        }
+    }
+}
+
+/// Modal to enter the DC for a check, which then makes the roll against it.
+pub struct CheckModal {
+    layout: view::Layout<State>,
+    eds: EditorState<i64>,
+    roll: roll::Roll,
+}
+
+impl CheckModal {
+    /// DC suggested if no check has been made yet this session.
+    const DEFAULT_DC: i64 = 15;
+
+    pub fn new(roll: roll::Roll, state: &State) -> Self {
+        let (eds, editor) =
+            IntEditor::new(state.last_dc.unwrap_or(Self::DEFAULT_DC));
+        let dimensions = Dims::length(2 + 2 + 2 + BORDER, 1 + BORDER);
+        let mut layout = view::Layout::new();
+        layout.add_el(editor);
+        Self {
+            layout: layout.modal("DC", dimensions, false),
+            eds,
+            roll,
+        }
+    }
+}
+
+impl Scene<State> for CheckModal {
+    fn layout(&self) -> &view::Layout<State> {
+        &self.layout
+    }
+
+    fn handle_key_press(&mut self, key: KeyCode, state: &mut State) -> Handler {
+        match key {
+            KeyCode::Enter => {
+                let dc = self.eds.get();
+                state.last_dc = Some(dc);
+                let roll = self.roll.clone().against(dc);
+                Handler::Replace(Box::new(RollModal::new(roll, state)))
+            }
+            KeyCode::Esc => Handler::Close,
+            _ => match view::Navigation::from_key_code(key) {
+                Some(view::Navigation::Left) => {
+                    self.eds.update(|dc| dc - 1);
+                    Handler::Consume
+                }
+                Some(view::Navigation::Right) => {
+                    self.eds.update(|dc| dc + 1);
+                    Handler::Consume
+                }
+                _ => Handler::Default,
+            },
+        }
+    }
+}
+
+/// Displays summary statistics and a histogram of the distribution of a
+/// roll, with the chance to meet an adjustable DC. Rolls with too many
+/// outcomes show an error instead.
+struct OddsDisplay {
+    roll_text: String,
+    distribution: Result<roll::Distribution, String>,
+    dc: EditorState<i64>,
+}
+
+impl OddsDisplay {
+    /// Maximum number of histogram bars to display.
+    const BUCKETS: usize = 16;
+
+    /// Lines of text above the histogram.
+    const SUMMARY_LINES: usize = 4;
+
+    fn height(&self) -> u16 {
+        match &self.distribution {
+            Ok(dist) => {
+                (Self::SUMMARY_LINES + dist.buckets(Self::BUCKETS).len()) as u16
+            }
+            Err(_) => 2,
+        }
+    }
+}
+
+/// Format a probability as a percentage.
+fn format_chance(chance: f64) -> String {
+    format!("{:.1}%", chance * 100.0)
+}
+
+impl ElSimp<State> for OddsDisplay {
+    fn dimensions(&self) -> Dims {
+        Dims::new(Constraint::Min(40), Constraint::Length(self.height()))
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        _state: &State,
+        _selected: bool,
+    ) {
+        let dist = match &self.distribution {
+            Ok(dist) => dist,
+            Err(message) => {
+                let lines = vec![
+                    Line::from(self.roll_text.as_str()),
+                    Line::from(message.as_str()).red(),
+                ];
+                frame.render_widget(Paragraph::new(lines), area);
+                return;
+            }
+        };
+        let dc = self.dc.get();
+        let percentiles = [0.1, 0.25, 0.5, 0.75, 0.9]
+            .iter()
+            .map(|&p| roll::format_number(dist.percentile(p)))
+            .collect::<Vec<String>>()
+            .join(" / ");
+        let mut lines = vec![
+            Line::from(self.roll_text.as_str()),
+            Line::from(format!(
+                "Mean {:.2}  Min {}  Max {}",
+                dist.mean(),
+                roll::format_number(dist.min()),
+                roll::format_number(dist.max())
+            )),
+            Line::from(format!("10/25/50/75/90%: {percentiles}")),
+            Line::from(format!(
+                "DC < {dc} >: {}",
+                format_chance(dist.chance_at_least(dc as f64))
+            )),
+        ];
+
+        let buckets = dist.buckets(Self::BUCKETS);
+        let labels: Vec<String> = buckets
+            .iter()
+            .map(|&(low, high, _)| {
+                if low == high {
+                    roll::format_number(low)
+                } else {
+                    format!(
+                        "{}-{}",
+                        roll::format_number(low),
+                        roll::format_number(high)
+                    )
+                }
+            })
+            .collect();
+        let label_width = labels.iter().map(|l| l.len()).max().unwrap_or(0);
+        let most_likely = buckets.iter().map(|b| b.2).fold(0.0, f64::max);
+        // Leave room for the label, percentage and spaces between them.
+        let bar_width =
+            (area.width as usize).saturating_sub(label_width + 8).max(1);
+        for (label, (_, _, chance)) in labels.iter().zip(&buckets) {
+            let length = if most_likely > 0.0 {
+                (chance / most_likely * bar_width as f64).round() as usize
+            } else {
+                0
+            };
+            lines.push(Line::from(format!(
+                "{label:>label_width$} {:<bar_width$} {:>6}",
+                "#".repeat(length),
+                format_chance(*chance)
+            )));
+        }
+
+        frame.render_widget(Paragraph::new(lines), area);
+    }
+}
+
+/// Modal showing the odds of the outcomes of a roll. Left and right adjust
+/// the DC to show the chance of meeting.
+pub struct OddsModal {
+    layout: view::Layout<State>,
+    eds: EditorState<i64>,
+}
+
+impl OddsModal {
+    pub fn new(roll: roll::Roll, state: &State) -> Self {
+        let roll = state.bind_roll(roll.clone()).unwrap_or(roll);
+        let dc = roll
+            .dc()
+            .or(state.last_dc)
+            .unwrap_or(CheckModal::DEFAULT_DC);
+        let eds = EditorState::new(dc);
+        let el = OddsDisplay {
+            roll_text: roll.format_resolved(),
+            distribution: roll.distribution(),
+            dc: eds.clone(),
+        };
+        let dimensions = Dims::new(
+            Constraint::Length(48 + BORDER),
+            Constraint::Length(el.height() + BORDER),
+        );
+        let mut layout = view::Layout::new();
+        layout.add_el(el);
+        Self {
+            layout: layout.modal("Odds", dimensions, false),
+            eds,
+        }
+    }
+}
+
+impl Scene<State> for OddsModal {
+    fn layout(&self) -> &view::Layout<State> {
+        &self.layout
+    }
+
+    fn handle_key_press(
+        &mut self,
+        key: KeyCode,
+        _state: &mut State,
+    ) -> Handler {
+        match key {
+            KeyCode::Enter | KeyCode::Esc => Handler::Close,
+            _ => match view::Navigation::from_key_code(key) {
+                Some(view::Navigation::Left) => {
+                    self.eds.update(|dc| dc - 1);
+                    Handler::Consume
+                }
+                Some(view::Navigation::Right) => {
+                    self.eds.update(|dc| dc + 1);
+                    Handler::Consume
+                }
+                _ => Handler::Default,
+            },
+        }
+    }
+}
+
+/// Reason a roll entered couldn't be used, with the byte range of the text at
+/// fault.
+struct RollInputError {
+    span: std::ops::Range<usize>,
+    message: String,
+}
+
+/// Parse a roll entered by the user and look up the sheet variables it uses.
+fn parse_roll_input(
+    text: &str,
+    state: &State,
+) -> Result<roll::Roll, RollInputError> {
+    let roll = roll::Roll::parse(text).map_err(|e| RollInputError {
+        span: e.offset..e.offset + e.length,
+        message: e.message,
+    })?;
+    state.bind_roll(roll).map_err(|name| {
+        let var = format!("@{name}");
+        let start = text.find(&var).unwrap_or(0);
+        RollInputError {
+            span: start..start + var.len(),
+            message: format!("Unknown variable {var}."),
+        }
+    })
+}
+
+/// Displays a roll being entered, with a preview of the parsed roll below it
+/// or the reason it is invalid, with the invalid text highlighted.
+struct RollInputDisplay {
+    value: EditorState<String>,
+
+    /// Whether the roll will be made as a critical hit, in which case the
+    /// preview shows the roll after applying the critical rule.
+    critical: bool,
+}
+
+impl ElSimp<State> for RollInputDisplay {
+    fn dimensions(&self) -> Dims {
+        let height = if self.critical { 3 } else { 2 };
+        Dims::new(Constraint::Min(16), Constraint::Length(height))
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        state: &State,
+        _selected: bool,
+    ) {
+        let text = self.value.get();
+        let mut lines = match parse_roll_input(&text, state) {
+            Ok(roll) => {
+                let roll = if self.critical {
+                    roll.critical(state.crit_rule)
+                } else {
+                    roll
+                };
+                vec![
+                    text.to_line(),
+                    Line::from(format!("= {}", roll.format_resolved()))
+                        .dark_gray(),
+                ]
+            }
+            Err(_) if text.trim().is_empty() => {
+                vec![Line::default(), Line::from("e.g. 2d6 + @str").dark_gray()]
+            }
+            Err(RollInputError { span, message }) => {
+                // Highlight a space after the text if it ended early.
+                let bad = if span.is_empty() {
+                    " "
+                } else {
+                    &text[span.clone()]
+                };
+                vec![
+                    Line::from(vec![
+                        Span::raw(&text[..span.start]),
+                        Span::raw(bad).on_red(),
+                        Span::raw(&text[span.end..]),
+                    ]),
+                    Line::from(message).red(),
+                ]
+            }
+        };
+        if self.critical {
+            lines.push(Line::from(format!(
+                "Critical: {} (up/down to change)",
+                state.crit_rule.format()
+            )));
+        }
+        frame.render_widget(Paragraph::new(lines), area);
+    }
+}
+
+pub struct RollEditorModal {
+    layout: view::Layout<State>,
+    value: EditorState<String>,
+    input: tui_input::Input,
+
+    /// Handler to save the expression entered. If not set, the expression is
+    /// rolled instead.
+    apply_to_state: Option<EditorSubmitHandler<String>>,
+
+    /// Whether the expression is rolled as a critical hit.
+    critical: bool,
+}
+
+impl RollEditorModal {
+    pub fn new() -> Self {
+        Self::create("Roll", String::new(), None, false)
+    }
+
+    /// Create a modal to enter damage to roll as a critical hit, starting
+    /// from the provided expression.
+    pub fn critical(initial_value: String) -> Self {
+        Self::create("Critical damage", initial_value, None, true)
+    }
+
+    /// Create a modal to edit a roll expression, which is checked to be a
+    /// valid roll before being passed to the handler.
+    pub fn editor(
+        title: &str,
+        initial_value: String,
+        handler: EditorSubmitHandler<String>,
+    ) -> Self {
+        Self::create(title, initial_value, Some(handler), false)
+    }
+
+    fn create(
+        title: &str,
+        initial_value: String,
+        apply_to_state: Option<EditorSubmitHandler<String>>,
+        critical: bool,
+    ) -> Self {
+        let input = tui_input::Input::new(initial_value.clone());
+        let value = EditorState::new(initial_value);
+        let el = RollInputDisplay {
+            value: value.clone(),
+            critical,
+        };
+        let mut layout = view::Layout::new();
+        layout.add_el(el);
+        let height = if critical { 3 } else { 2 };
+        let layout = layout.modal(
+            title,
+            Dims::new(Constraint::Min(40), Constraint::Length(height + BORDER)),
+            false,
+        );
+
+        Self {
+            layout,
+            value,
+            input,
+            apply_to_state,
+            critical,
+        }
+    }
+
+    fn roll(&self, state: &State) -> Result<roll::Roll, RollInputError> {
+        let roll = parse_roll_input(&self.value.get(), state)?;
+        if self.critical {
+            Ok(roll.critical(state.crit_rule))
+        } else {
+            Ok(roll)
+        }
+    }
+}
+
+impl Scene<State> for RollEditorModal {
+    fn layout(&self) -> &view::Layout<State> {
+        &self.layout
+    }
+
+    fn handle(
+        &mut self,
+        event: Event,
+        state: &mut State,
+        _selected: view::ElPos,
+    ) -> Handler {
+        if let Event::Key(evt) = event
+            && evt.kind == KeyEventKind::Press
+        {
+            let result = self.handle_key_press(evt.code, state);
+            if !matches!(result, Handler::Default) {
+                return result;
+            }
+        }
+
+        match self.input.handle_event(&event) {
+            Some(changes) => {
+                if changes.value {
+                    self.value.set(self.input.value().to_string());
+                }
+                Handler::Consume
+            }
+            None => Handler::Default,
+        }
+    }
+
+    fn handle_key_press(&mut self, key: KeyCode, state: &mut State) -> Handler {
+        match key {
+            // Errors are shown inline, so the editor stays open to fix them.
+            KeyCode::Enter => {
+                match (self.roll(state), &mut self.apply_to_state) {
+                    (Ok(_), Some(apply_to_state)) => {
+                        apply_to_state(self.value.get(), state);
+                        Handler::Close
+                    }
+                    (Ok(roll), None) => {
+                        Handler::Replace(Box::new(RollModal::new(roll, state)))
+                    }
+                    (Err(_), _) => Handler::Consume,
+                }
+            }
+            KeyCode::Tab => match self.roll(state) {
+                Ok(roll) => {
+                    Handler::Open(Box::new(OddsModal::new(roll, state)))
+                }
+                Err(_) => Handler::Consume,
+            },
+            KeyCode::Up | KeyCode::Down if self.critical => {
+                state.crit_rule = state.crit_rule.toggle();
+                Handler::Consume
+            }
             KeyCode::Esc => Handler::Close,
             _ => Handler::Default,
         }
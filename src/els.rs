@@ -1,5 +1,6 @@
 use ratatui::{
     Frame,
+    crossterm::event::KeyCode,
     layout::{Constraint, Direction, Position, Rect},
     style::{Color, Stylize},
     text::{Line, ToLine},
@@ -7,7 +8,7 @@ use ratatui::{
 };
 
 use crate::{
-    Handler, SheetState, editors, els,
+    Handler, SheetState, conditions, editors, els, modifiers,
     roll::{self, Roll},
     scenes,
     stats::{self, Stat},
@@ -113,6 +114,17 @@ impl StatEl {
     pub fn new(stat: Stat) -> Self {
         Self(stat)
     }
+
+    /// Roll for a check using this stat.
+    fn roll(&self, state: &State) -> Roll {
+        let modifier = state.stats.modifier(self.0);
+        let roll =
+            Roll::new(1, 20).plus_labelled(modifier as f64, self.0.short());
+        state
+            .resolve_modifiers(modifiers::Target::Stat(self.0))
+            .apply(roll)
+            .labelled(format!("{:?}", self.0))
+    }
 }
 
 impl ElSimp<State> for StatEl {
@@ -148,16 +160,188 @@ impl ElSimp<State> for StatEl {
     }
 
     fn handle_roll(&self, state: &State) -> Handler {
-        let modifier = state.stats.modifier(self.0);
-        let modal =
-            editors::RollModal::new(Roll::new(1, 20).plus(modifier as f64));
+        let modal = editors::RollModal::new(self.roll(state), state);
+        Handler::Open(Box::new(modal))
+    }
+
+    fn handle_check(&self, state: &State) -> Handler {
+        let modal = editors::CheckModal::new(self.roll(state), state);
         Handler::Open(Box::new(modal))
     }
+
+    fn handle_odds(&self, state: &State) -> Handler {
+        let modal = editors::OddsModal::new(self.roll(state), state);
+        Handler::Open(Box::new(modal))
+    }
+}
+
+/// Element showing Perception and the current initiative. Initiative can be
+/// rolled using Perception or any skill.
+pub struct PerceptionEl;
+
+impl PerceptionEl {
+    const ROWS: usize = 2;
+
+    fn roll(&self, state: &State, selected: usize) -> Option<Roll> {
+        (selected == 0).then(|| state.perception.roll(state))
+    }
+}
+
+impl ElGroup<State> for PerceptionEl {
+    fn direction(&self) -> Direction {
+        Direction::Vertical
+    }
+
+    fn dimensions(&self, _state: &State) -> Dims {
+        Dims::new(
+            Constraint::Min(16),
+            Constraint::Length(Self::ROWS as u16 + BORDER),
+        )
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        state: &State,
+        selected: Option<usize>,
+    ) {
+        let proficiency = state.perception.proficiency;
+        let pstr = if proficiency == stats::Proficiency::Untrained {
+            String::from(" ")
+        } else {
+            format!("{proficiency:?}")[..1].to_string()
+        };
+        let initiative = match state.initiative {
+            Some(initiative) => initiative.to_string(),
+            None => "-".to_string(),
+        };
+        let rows = [
+            Row::new([
+                stats::Perception::NAME.to_string(),
+                pstr,
+                format_modifier(state.perception.modifier(state)),
+            ]),
+            Row::new(["Initiative".to_string(), String::new(), initiative]),
+        ];
+        let widget = Table::new(
+            rows.into_iter()
+                .enumerate()
+                .map(|(i, row)| style_selected(row, selected == Some(i))),
+            [Constraint::Fill(1), Constraint::Max(1), Constraint::Max(3)],
+        )
+        .block(Block::bordered());
+        frame.render_widget(widget, area);
+    }
+
+    fn handle_select(&self, state: &State, selected: usize) -> Handler {
+        match selected {
+            0 => Handler::Open(Box::new(editors::ProficiencyModal::new(
+                stats::Perception::NAME,
+                state.perception.proficiency,
+                Box::new(|prof, state| state.perception.proficiency = prof),
+            ))),
+            _ => {
+                Handler::Open(Box::new(editors::OptionModal::initiative(state)))
+            }
+        }
+    }
+
+    fn handle_roll(&self, state: &State, selected: usize) -> Handler {
+        match self.roll(state, selected) {
+            Some(roll) => {
+                Handler::Open(Box::new(editors::RollModal::new(roll, state)))
+            }
+            None => self.handle_select(state, selected),
+        }
+    }
+
+    fn handle_check(&self, state: &State, selected: usize) -> Handler {
+        if let Some(roll) = self.roll(state, selected) {
+            let modal = editors::CheckModal::new(roll, state);
+            Handler::Open(Box::new(modal))
+        } else {
+            Handler::Default
+        }
+    }
+
+    fn handle_odds(&self, state: &State, selected: usize) -> Handler {
+        if let Some(roll) = self.roll(state, selected) {
+            let modal = editors::OddsModal::new(roll, state);
+            Handler::Open(Box::new(modal))
+        } else {
+            Handler::Default
+        }
+    }
+
+    fn child_count(&self, _state: &State) -> usize {
+        Self::ROWS
+    }
+
+    fn child_pos(
+        &self,
+        area: Rect,
+        _state: &State,
+        selected: usize,
+    ) -> (u16, u16) {
+        let x = area.x + area.width / 2;
+        let y = area.top() + selected as u16 + BORDER / 2;
+        (x, y)
+    }
+
+    fn child_at_pos(
+        &self,
+        area: Rect,
+        _state: &State,
+        _x: u16,
+        y: u16,
+    ) -> usize {
+        let y_offset = y.saturating_sub(area.y + BORDER / 2);
+        (y_offset as usize).min(Self::ROWS - 1)
+    }
 }
 
 /// Element that renders a table of all skills present in the state.
 pub struct SkillsEl;
 
+impl SkillsEl {
+    /// Roll for a check using the selected skill.
+    fn roll(&self, state: &State, selected: usize) -> Option<Roll> {
+        Some(state.skills.0.get(selected)?.roll(state))
+    }
+
+    /// Open a modal to enter the topic of a new Lore skill.
+    fn handle_add(&self) -> Handler {
+        Handler::Open(Box::new(editors::StringEditorModal::checked(
+            "New Lore",
+            String::new(),
+            Box::new(|topic, state| state.skills.add_lore(&topic)),
+        )))
+    }
+
+    fn handle_rename(&self, state: &State, selected: usize) -> Handler {
+        match state.skills.0.get(selected) {
+            Some(skill) if skill.lore => {
+                Handler::Open(Box::new(editors::StringEditorModal::checked(
+                    "Rename Lore",
+                    skill.name.clone(),
+                    Box::new(move |topic, state| {
+                        state.skills.rename_lore(selected, &topic)
+                    }),
+                )))
+            }
+            _ => Handler::Default,
+        }
+    }
+
+    fn handle_delete(&self, state: &mut State, selected: usize) -> Handler {
+        match state.skills.remove_lore(selected) {
+            Some(_) => Handler::Consume,
+            None => Handler::Default,
+        }
+    }
+}
+
 impl ElGroup<State> for SkillsEl {
     fn direction(&self) -> Direction {
         Direction::Vertical
@@ -177,7 +361,7 @@ impl ElGroup<State> for SkillsEl {
 
         Dims::new(
             Constraint::Min(min_width),
-            Constraint::Length(state.skills.0.len() as u16 + BORDER),
+            Constraint::Length(self.child_count(state) as u16 + BORDER),
         )
     }
 
@@ -189,26 +373,35 @@ impl ElGroup<State> for SkillsEl {
         selected: Option<usize>,
     ) {
         let widget = Table::new(
-            state.skills.0.iter().enumerate().map(|(i, skill)| {
-                let proficiency = skill.proficiency;
-                let pstr = if proficiency == stats::Proficiency::Untrained {
-                    String::from(" ")
-                } else {
-                    format!("{proficiency:?}")
-                        .chars()
-                        .next()
-                        .unwrap()
-                        .to_string()
-                };
+            state
+                .skills
+                .0
+                .iter()
+                .enumerate()
+                .map(|(i, skill)| {
+                    let proficiency = skill.proficiency;
+                    let pstr = if proficiency == stats::Proficiency::Untrained {
+                        String::from(" ")
+                    } else {
+                        format!("{proficiency:?}")
+                            .chars()
+                            .next()
+                            .unwrap()
+                            .to_string()
+                    };
 
-                let row = Row::new([
-                    Cell::new(skill.name.as_str()),
-                    Cell::new(skill.stat.short()),
-                    Cell::new(pstr),
-                    Cell::new(els::format_modifier(skill.modifier(state))),
-                ]);
-                style_selected(row, selected == Some(i))
-            }),
+                    let row = Row::new([
+                        Cell::new(skill.name.as_str()),
+                        Cell::new(skill.stat.short()),
+                        Cell::new(pstr),
+                        Cell::new(els::format_modifier(skill.modifier(state))),
+                    ]);
+                    style_selected(row, selected == Some(i))
+                })
+                .chain(std::iter::once(style_selected(
+                    Row::new(["+ New Lore"]),
+                    selected == Some(state.skills.0.len()),
+                ))),
             [
                 Constraint::Fill(1),
                 Constraint::Max(3),
@@ -220,20 +413,48 @@ impl ElGroup<State> for SkillsEl {
         frame.render_widget(widget, area);
     }
 
+    fn handle_key_press(
+        &self,
+        code: KeyCode,
+        state: &mut State,
+        selected: usize,
+    ) -> Handler {
+        match code {
+            KeyCode::Char('n') => self.handle_rename(state, selected),
+            KeyCode::Delete => self.handle_delete(state, selected),
+            _ => self.handle_default_key_press(code, state, selected),
+        }
+    }
+
     fn handle_select(&self, state: &State, selected: usize) -> Handler {
         if let Some(skill) = state.skills.0.get(selected) {
-            let modal = editors::SkillModal::new(&skill.name, state);
+            Handler::Open(editors::skill_modal(&skill.name, state))
+        } else {
+            self.handle_add()
+        }
+    }
+
+    fn handle_roll(&self, state: &State, selected: usize) -> Handler {
+        if let Some(roll) = self.roll(state, selected) {
+            let modal = editors::RollModal::new(roll, state);
             Handler::Open(Box::new(modal))
         } else {
             Handler::Default
         }
     }
 
-    fn handle_roll(&self, state: &State, selected: usize) -> Handler {
-        if let Some(skill) = state.skills.0.get(selected) {
-            let modifier = skill.modifier(state);
-            let modal =
-                editors::RollModal::new(Roll::new(1, 20).plus(modifier as f64));
+    fn handle_check(&self, state: &State, selected: usize) -> Handler {
+        if let Some(roll) = self.roll(state, selected) {
+            let modal = editors::CheckModal::new(roll, state);
+            Handler::Open(Box::new(modal))
+        } else {
+            Handler::Default
+        }
+    }
+
+    fn handle_odds(&self, state: &State, selected: usize) -> Handler {
+        if let Some(roll) = self.roll(state, selected) {
+            let modal = editors::OddsModal::new(roll, state);
             Handler::Open(Box::new(modal))
         } else {
             Handler::Default
@@ -241,7 +462,8 @@ impl ElGroup<State> for SkillsEl {
     }
 
     fn child_count(&self, state: &State) -> usize {
-        state.skills.0.len()
+        // Skills and a row to add a new Lore skill.
+        state.skills.0.len() + 1
     }
 
     fn child_pos(
@@ -264,126 +486,104 @@ impl ElGroup<State> for SkillsEl {
     ) -> usize {
         let y_offset = y - area.y;
         let table_index = y_offset as usize + 1;
-        table_index.min(state.skills.0.len().saturating_sub(1))
-    }
-}
-
-pub fn format_modifier(modifier: i64) -> String {
-    if modifier < 0 {
-        modifier.to_string()
-    } else {
-        format!("+{modifier}")
+        table_index.min(state.skills.0.len())
     }
 }
 
-pub struct RollDisplay {
-    dimensions: Dims,
-    roll_text: String,
-    result_text: String,
-}
-
-impl RollDisplay {
-    pub fn new(outcome: &roll::RollOutcome) -> Self {
-        let roll_text = outcome.format_roll();
-        let result_text = format!(
-            "{} ({})",
-            outcome.format_value(),
-            outcome.format_results()
-        );
-        let width = roll_text.len().max(result_text.len()) as u16;
-        let dimensions =
-            Dims::new(Constraint::Length(width), Constraint::Length(2));
-        Self {
-            dimensions,
-            roll_text,
-            result_text,
-        }
-    }
-}
+/// Table of hit points, with rows to apply damage or healing and to edit the
+/// values maximum HP is derived from.
+pub struct HitPointsEl;
 
-impl ElSimp<State> for RollDisplay {
-    fn dimensions(&self) -> Dims {
-        self.dimensions
-    }
+impl HitPointsEl {
+    const ROWS: usize = 5;
 
-    fn render(
-        &self,
-        frame: &mut Frame,
-        area: Rect,
-        _state: &State,
-        selected: bool,
-    ) {
-        let widget = Paragraph::new(vec![
-            self.roll_text.to_line(),
-            self.result_text.to_line(),
-        ])
-        .centered();
-        frame.render_widget(style_selected(widget, selected), area);
+    /// Open an editor for the value on the selected row.
+    fn edit(&self, state: &State, selected: usize) -> Handler {
+        let hp = &state.hp;
+        let modal = match selected {
+            0 => {
+                return Handler::Open(Box::new(editors::HitPointsModal::new()));
+            }
+            1 => editors::IntEditorModal::new(
+                "Temp HP",
+                hp.temp,
+                Box::new(|temp, state| state.hp.temp = temp.max(0)),
+            ),
+            2 => editors::IntEditorModal::new(
+                "Ancestry HP",
+                hp.ancestry,
+                Box::new(|ancestry, state| state.hp.ancestry = ancestry),
+            ),
+            3 => editors::IntEditorModal::new(
+                "Class HP",
+                hp.class,
+                Box::new(|class, state| state.hp.class = class),
+            ),
+            // Setting the maximum to the derived value removes the override.
+            4 => editors::IntEditorModal::new(
+                "Max HP",
+                hp.max(state),
+                Box::new(|max, state| {
+                    let derived = state.hp.derived_max(state);
+                    state.hp.max_override = (max != derived).then_some(max);
+                }),
+            ),
+            _ => return Handler::Default,
+        };
+        Handler::Open(Box::new(modal))
     }
 }
 
-pub struct Dice;
-
-impl Dice {
-    const DICE: &[u32] = &[4, 6, 8, 10, 12, 20];
-
-    fn iter_layout(
-        &self,
-        area: Rect,
-    ) -> impl Iterator<Item = (usize, Rect, String)> {
-        let mut labels: Vec<String> =
-            Self::DICE.iter().map(|d| format!("d{d}")).collect();
-        labels.push("Custom".to_string());
-        let areas = ratatui::prelude::Layout::new(
-            Direction::Horizontal,
-            vec![Constraint::Fill(1); Dice::DICE.len() + 1],
-        )
-        .split(area)
-        .to_vec();
-        labels
-            .into_iter()
-            .zip(areas)
-            .enumerate()
-            .map(|(i, (label, area))| (i, area, label))
+impl ElGroup<State> for HitPointsEl {
+    fn direction(&self) -> Direction {
+        Direction::Vertical
     }
-}
 
-impl ElGroup<State> for Dice {
     fn dimensions(&self, _state: &State) -> Dims {
         Dims::new(
-            Constraint::Min(4 * Dice::DICE.len() as u16 + BORDER),
-            Constraint::Length(BORDER + 1),
+            Constraint::Min(16),
+            Constraint::Length(Self::ROWS as u16 + BORDER),
         )
     }
 
-    fn direction(&self) -> Direction {
-        Direction::Horizontal
-    }
-
     fn render(
         &self,
         frame: &mut Frame,
         area: Rect,
-        _state: &State,
+        state: &State,
         selected: Option<usize>,
     ) {
-        for (i, area, label) in self.iter_layout(area) {
-            let area = if i == Dice::DICE.len() - 1 {
-                area
-            } else {
-                Rect::new(area.x, area.y, area.width + 1, area.height)
-            };
-            let widget = Paragraph::new(style_selected(
-                label.to_line(),
-                selected == Some(i),
-            ))
-            .block(Block::bordered());
-            frame.render_widget(widget, area);
-        }
+        let hp = &state.hp;
+        let max = match hp.max_override {
+            Some(max) => max.to_string(),
+            None => format!("{} (auto)", hp.max(state)),
+        };
+        let rows = [
+            ("HP", format!("{}/{}", hp.current(state), hp.max(state))),
+            ("Temp", hp.temp.to_string()),
+            ("Ancestry", hp.ancestry.to_string()),
+            ("Class", format!("{}/level", hp.class)),
+            ("Max", max),
+        ];
+        let widget = Table::new(
+            rows.into_iter().enumerate().map(|(i, (name, value))| {
+                style_selected(
+                    Row::new([name.to_string(), value]),
+                    selected == Some(i),
+                )
+            }),
+            [Constraint::Length(8), Constraint::Fill(1)],
+        )
+        .block(Block::bordered().title("Hit Points"));
+        frame.render_widget(widget, area);
+    }
+
+    fn handle_select(&self, state: &State, selected: usize) -> Handler {
+        self.edit(state, selected)
     }
 
     fn child_count(&self, _state: &State) -> usize {
-        Dice::DICE.len() + 1
+        Self::ROWS
     }
 
     fn child_pos(
@@ -392,51 +592,1494 @@ impl ElGroup<State> for Dice {
         _state: &State,
         selected: usize,
     ) -> (u16, u16) {
-        for (i, d_area, _) in self.iter_layout(area) {
-            if i == selected {
-                return centre_of(d_area);
-            }
-        }
-        centre_of(area)
+        let x = area.x + area.width / 2;
+        let y = area.top() + selected as u16 + BORDER / 2;
+        (x, y)
     }
 
     fn child_at_pos(
         &self,
         area: Rect,
         _state: &State,
-        x: u16,
+        _x: u16,
         y: u16,
     ) -> usize {
-        for (i, d_area, _) in self.iter_layout(area) {
-            if d_area.contains(Position::new(x, y)) {
-                return i;
-            }
-        }
-        0
+        let y_offset = y.saturating_sub(area.y + BORDER / 2);
+        (y_offset as usize).min(Self::ROWS - 1)
     }
+}
 
-    fn handle_roll(&self, _state: &State, selected: usize) -> Handler {
-        if let Some(d) = Dice::DICE.get(selected).copied() {
-            Handler::Open(Box::new(editors::RollModal::new(Roll::new(1, d))))
-        } else {
-            // Custom
-            Handler::Open(Box::new(editors::RollEditorModal::new()))
-        }
-    }
+/// Warning shown when the character has died.
+pub struct DeathWarning;
 
-    fn handle_select(&self, state: &State, selected: usize) -> Handler {
-        self.handle_roll(state, selected)
+impl DeathWarning {
+    fn text(state: &State) -> String {
+        format!(
+            "DEAD: dying {} reached {}",
+            state.death.dying,
+            state.death.death_threshold()
+        )
     }
 }
 
-pub struct RollHistory {
-    max_rolls_to_display: usize,
-}
+impl ElSimp<State> for DeathWarning {
+    fn dimensions(&self) -> Dims {
+        Dims::new(Constraint::Min(24), Constraint::Length(1))
+    }
 
-impl RollHistory {
-    pub fn new(max_rolls_to_display: usize) -> Self {
-        Self {
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        state: &State,
+        _selected: bool,
+    ) {
+        frame.render_widget(Line::from(Self::text(state)).red().bold(), area);
+    }
+}
+
+/// Table of the dying, wounded and doomed conditions. Recovery checks are
+/// rolled from here.
+pub struct DeathEl;
+
+impl DeathEl {
+    const ROWS: usize = 3;
+
+    /// Make a recovery check, applying it to dying and recording the roll.
+    fn handle_recovery(&self, state: &mut State) -> Handler {
+        let before = state.death.dying;
+        let outcome = state.death.recovery_check().resolve(&state.dice);
+        if let Some(degree) = outcome.degree() {
+            state.death.recover(degree);
+        }
+        let modal =
+            editors::RecoveryCheckModal::resolved(&outcome, before, state);
+        state.rolls.push(outcome);
+        Handler::Open(Box::new(modal))
+    }
+}
+
+impl ElGroup<State> for DeathEl {
+    fn direction(&self) -> Direction {
+        Direction::Vertical
+    }
+
+    fn dimensions(&self, _state: &State) -> Dims {
+        Dims::new(
+            Constraint::Min(16),
+            Constraint::Length(Self::ROWS as u16 + BORDER),
+        )
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        state: &State,
+        selected: Option<usize>,
+    ) {
+        let death = &state.death;
+        let rows = [
+            (
+                "Dying",
+                format!("{}/{}", death.dying, death.death_threshold()),
+            ),
+            ("Wounded", death.wounded.to_string()),
+            ("Doomed", death.doomed.to_string()),
+        ];
+        let mut block = Block::bordered();
+        block = if death.is_dead() {
+            block.title(Line::from(DeathWarning::text(state)).red().bold())
+        } else {
+            block.title("Death (r: recovery check)")
+        };
+        let widget = Table::new(
+            rows.into_iter().enumerate().map(|(i, (name, value))| {
+                style_selected(
+                    Row::new([name.to_string(), value]),
+                    selected == Some(i),
+                )
+            }),
+            [Constraint::Length(8), Constraint::Fill(1)],
+        )
+        .block(block);
+        frame.render_widget(widget, area);
+    }
+
+    fn handle_key_press(
+        &self,
+        code: KeyCode,
+        state: &mut State,
+        selected: usize,
+    ) -> Handler {
+        match code {
+            KeyCode::Char('r') if state.death.dying > 0 => {
+                self.handle_recovery(state)
+            }
+            _ => self.handle_default_key_press(code, state, selected),
+        }
+    }
+
+    fn handle_select(&self, state: &State, selected: usize) -> Handler {
+        let death = &state.death;
+        let modal = match selected {
+            0 => editors::IntEditorModal::new(
+                "Dying",
+                death.dying,
+                Box::new(|dying, state| state.death.dying = dying.max(0)),
+            ),
+            1 => editors::IntEditorModal::new(
+                "Wounded",
+                death.wounded,
+                Box::new(|wounded, state| state.death.wounded = wounded.max(0)),
+            ),
+            2 => editors::IntEditorModal::new(
+                "Doomed",
+                death.doomed,
+                Box::new(|doomed, state| state.death.doomed = doomed.max(0)),
+            ),
+            _ => return Handler::Default,
+        };
+        Handler::Open(Box::new(modal))
+    }
+
+    fn child_count(&self, _state: &State) -> usize {
+        Self::ROWS
+    }
+
+    fn child_pos(
+        &self,
+        area: Rect,
+        _state: &State,
+        selected: usize,
+    ) -> (u16, u16) {
+        let x = area.x + area.width / 2;
+        let y = area.top() + selected as u16 + BORDER / 2;
+        (x, y)
+    }
+
+    fn child_at_pos(
+        &self,
+        area: Rect,
+        _state: &State,
+        _x: u16,
+        y: u16,
+    ) -> usize {
+        let y_offset = y.saturating_sub(area.y + BORDER / 2);
+        (y_offset as usize).min(Self::ROWS - 1)
+    }
+}
+
+/// Element showing the saving throws side by side.
+pub struct SavesEl;
+
+impl SavesEl {
+    fn save(selected: usize) -> Option<stats::Save> {
+        stats::Save::SAVES.get(selected).copied()
+    }
+
+    /// Roll the selected save.
+    fn roll(&self, state: &State, selected: usize) -> Option<Roll> {
+        Some(state.saves.roll(Self::save(selected)?, state))
+    }
+
+    /// Areas of each save within the element.
+    fn cells(area: Rect) -> std::rc::Rc<[Rect]> {
+        ratatui::layout::Layout::horizontal(
+            stats::Save::SAVES.iter().map(|_| Constraint::Fill(1)),
+        )
+        .split(Block::bordered().inner(area))
+    }
+}
+
+impl ElGroup<State> for SavesEl {
+    fn direction(&self) -> Direction {
+        Direction::Horizontal
+    }
+
+    fn dimensions(&self, _state: &State) -> Dims {
+        // Each save has a name, proficiency and modifier, e.g. Fort T +5.
+        let width = stats::Save::SAVES.len() as u16 * 10;
+        Dims::new(Constraint::Min(width + BORDER), Constraint::Length(3))
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        state: &State,
+        selected: Option<usize>,
+    ) {
+        frame.render_widget(Block::bordered().title("Saves"), area);
+        for (i, (save, cell)) in stats::Save::SAVES
+            .iter()
+            .zip(Self::cells(area).iter())
+            .enumerate()
+        {
+            let proficiency = state.saves.proficiency(*save);
+            let pstr = if proficiency == stats::Proficiency::Untrained {
+                String::from(" ")
+            } else {
+                format!("{proficiency:?}")[..1].to_string()
+            };
+            let text = format!(
+                "{} {pstr} {}",
+                save.short(),
+                els::format_modifier(state.saves.modifier(*save, state))
+            );
+            frame.render_widget(
+                style_selected(text.to_line(), selected == Some(i)),
+                *cell,
+            );
+        }
+    }
+
+    fn handle_select(&self, state: &State, selected: usize) -> Handler {
+        if let Some(save) = Self::save(selected) {
+            let modal = editors::ProficiencyModal::new(
+                &format!("{save:?}"),
+                state.saves.proficiency(save),
+                Box::new(move |prof, state| {
+                    state.saves.set_proficiency(save, prof)
+                }),
+            );
+            Handler::Open(Box::new(modal))
+        } else {
+            Handler::Default
+        }
+    }
+
+    fn handle_roll(&self, state: &State, selected: usize) -> Handler {
+        if let Some(roll) = self.roll(state, selected) {
+            let modal = editors::RollModal::new(roll, state);
+            Handler::Open(Box::new(modal))
+        } else {
+            Handler::Default
+        }
+    }
+
+    fn handle_check(&self, state: &State, selected: usize) -> Handler {
+        if let Some(roll) = self.roll(state, selected) {
+            let modal = editors::CheckModal::new(roll, state);
+            Handler::Open(Box::new(modal))
+        } else {
+            Handler::Default
+        }
+    }
+
+    fn handle_odds(&self, state: &State, selected: usize) -> Handler {
+        if let Some(roll) = self.roll(state, selected) {
+            let modal = editors::OddsModal::new(roll, state);
+            Handler::Open(Box::new(modal))
+        } else {
+            Handler::Default
+        }
+    }
+
+    fn child_count(&self, _state: &State) -> usize {
+        stats::Save::SAVES.len()
+    }
+
+    fn child_pos(
+        &self,
+        area: Rect,
+        _state: &State,
+        selected: usize,
+    ) -> (u16, u16) {
+        let cell = Self::cells(area)[selected];
+        (cell.x + cell.width / 2, cell.y)
+    }
+
+    fn child_at_pos(
+        &self,
+        area: Rect,
+        _state: &State,
+        x: u16,
+        _y: u16,
+    ) -> usize {
+        Self::cells(area)
+            .iter()
+            .position(|cell| x < cell.right())
+            .unwrap_or(stats::Save::SAVES.len() - 1)
+    }
+}
+
+/// Table showing AC and the armor it comes from, with rows to raise a shield
+/// and to edit the armor.
+pub struct ArmorEl;
+
+impl ArmorEl {
+    const ROWS: usize = 9;
+
+    fn rows(state: &State) -> [(&'static str, String); Self::ROWS] {
+        let ac = &state.ac;
+        let armor = &ac.armor;
+        let breakdown = ac
+            .breakdown(state)
+            .iter()
+            .map(|(label, value)| format!("{label} {value:+}"))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let shield = if ac.shield_raised {
+            "raised"
+        } else {
+            "lowered"
+        };
+        let dex_cap = match armor.dex_cap() {
+            Some(cap) => format_modifier(cap),
+            None => "none".to_string(),
+        };
+        [
+            ("AC", format!("{} ({breakdown})", ac.value(state))),
+            (
+                "Shield",
+                format!("{} {shield}", format_modifier(ac.shield_bonus)),
+            ),
+            ("Armor", armor.name.clone()),
+            ("Category", format!("{:?}", armor.category)),
+            ("Prof", format!("{:?}", ac.proficiency(armor.category))),
+            ("Item", format_modifier(armor.item_bonus)),
+            ("Dex cap", dex_cap),
+            ("Check", format!("-{}", armor.check_penalty)),
+            ("Speed", format!("-{} ft", armor.speed_penalty)),
+        ]
+    }
+}
+
+impl ElGroup<State> for ArmorEl {
+    fn direction(&self) -> Direction {
+        Direction::Vertical
+    }
+
+    fn dimensions(&self, _state: &State) -> Dims {
+        Dims::new(
+            Constraint::Min(24),
+            Constraint::Length(Self::ROWS as u16 + BORDER),
+        )
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        state: &State,
+        selected: Option<usize>,
+    ) {
+        let suppressed = state
+            .ac
+            .suppressed(state)
+            .iter()
+            .map(modifiers::Modifier::format)
+            .collect::<Vec<String>>()
+            .join(", ");
+        let widget = Table::new(
+            Self::rows(state).into_iter().enumerate().map(
+                |(i, (name, value))| {
+                    // Like rolls, AC notes the modifiers it suppressed.
+                    let mut line = Line::from(value);
+                    if i == 0 && !suppressed.is_empty() {
+                        line.push_span(
+                            format!(" Suppressed: {suppressed}").dark_gray(),
+                        );
+                    }
+                    style_selected(
+                        Row::new([Cell::from(name), Cell::from(line)]),
+                        selected == Some(i),
+                    )
+                },
+            ),
+            [Constraint::Length(8), Constraint::Fill(1)],
+        )
+        .block(Block::bordered().title("Armor Class"));
+        frame.render_widget(widget, area);
+    }
+
+    fn handle_select(&self, state: &State, selected: usize) -> Handler {
+        let ac = &state.ac;
+        let armor = &ac.armor;
+        let modal: Box<dyn view::Scene<State>> = match selected {
+            0 => return Handler::Default,
+            1 => Box::new(editors::IntEditorModal::new(
+                "Shield bonus",
+                ac.shield_bonus,
+                Box::new(|bonus, state| state.ac.shield_bonus = bonus),
+            )),
+            2 => Box::new(editors::StringEditorModal::new(
+                "Armor",
+                armor.name.clone(),
+                Box::new(|name, state| state.ac.armor.name = name),
+            )),
+            3 => return Handler::Default,
+            4 => {
+                let category = armor.category;
+                Box::new(editors::ProficiencyModal::new(
+                    &format!("{category:?} armor"),
+                    ac.proficiency(category),
+                    Box::new(move |prof, state| {
+                        state.ac.set_proficiency(category, prof)
+                    }),
+                ))
+            }
+            5 => Box::new(editors::IntEditorModal::new(
+                "Item bonus",
+                armor.item_bonus,
+                Box::new(|bonus, state| state.ac.armor.item_bonus = bonus),
+            )),
+            6 => Box::new(editors::IntEditorModal::new(
+                "Dex cap",
+                armor.dex_cap,
+                Box::new(|cap, state| state.ac.armor.dex_cap = cap.max(0)),
+            )),
+            7 => Box::new(editors::IntEditorModal::new(
+                "Check penalty",
+                armor.check_penalty,
+                Box::new(|penalty, state| {
+                    state.ac.armor.check_penalty = penalty.max(0)
+                }),
+            )),
+            8 => Box::new(editors::IntEditorModal::new(
+                "Speed penalty",
+                armor.speed_penalty,
+                Box::new(|penalty, state| {
+                    state.ac.armor.speed_penalty = penalty.max(0)
+                }),
+            )),
+            _ => return Handler::Default,
+        };
+        Handler::Open(modal)
+    }
+
+    fn handle_key_press(
+        &self,
+        code: KeyCode,
+        state: &mut State,
+        selected: usize,
+    ) -> Handler {
+        match (code, selected) {
+            // Raising a shield is toggled from AC or the shield itself.
+            (KeyCode::Enter | KeyCode::Char('s'), 0)
+            | (KeyCode::Char('s'), 1) => {
+                state.ac.shield_raised = !state.ac.shield_raised;
+                Handler::Consume
+            }
+            (KeyCode::Enter, 3) => {
+                state.ac.armor.category = state.ac.armor.category.next();
+                Handler::Consume
+            }
+            (KeyCode::Enter, _) => self.handle_select(state, selected),
+            _ => Handler::Default,
+        }
+    }
+
+    fn child_count(&self, _state: &State) -> usize {
+        Self::ROWS
+    }
+
+    fn child_pos(
+        &self,
+        area: Rect,
+        _state: &State,
+        selected: usize,
+    ) -> (u16, u16) {
+        let x = area.x + area.width / 2;
+        let y = area.top() + selected as u16 + BORDER / 2;
+        (x, y)
+    }
+
+    fn child_at_pos(
+        &self,
+        area: Rect,
+        _state: &State,
+        _x: u16,
+        y: u16,
+    ) -> usize {
+        let y_offset = y.saturating_sub(area.y + BORDER / 2);
+        (y_offset as usize).min(Self::ROWS - 1)
+    }
+}
+
+/// Table of the ability boosts and flaws from each part of the character's
+/// build. Left and right select a stat, and boosts and flaws are toggled on
+/// the selected stat.
+pub struct BuildTable {
+    /// Selected column, with 0 the name of the source and each stat after.
+    column: std::cell::Cell<usize>,
+}
+
+impl BuildTable {
+    const SOURCES: &[&str] = &["Ancestry", "Background", "Class"];
+
+    pub fn new() -> Self {
+        Self {
+            column: std::cell::Cell::new(1),
+        }
+    }
+
+    fn source(
+        build: &mut stats::Build,
+        row: usize,
+    ) -> Option<&mut stats::BoostSource> {
+        match row {
+            0 => Some(&mut build.ancestry),
+            1 => Some(&mut build.background),
+            2 => Some(&mut build.class),
+            _ => None,
+        }
+    }
+
+    /// Level of the free boosts on the provided row, if it is one.
+    fn level(row: usize) -> Option<i64> {
+        let index = row.checked_sub(Self::SOURCES.len())?;
+        stats::Build::FREE_BOOST_LEVELS.get(index).copied()
+    }
+
+    fn stat(&self) -> Option<Stat> {
+        Stat::STATS.get(self.column.get().checked_sub(1)?).copied()
+    }
+
+    /// Toggle a boost, or a flaw if flaw is set, on the selected stat.
+    fn toggle(&self, state: &mut State, row: usize, flaw: bool) -> Handler {
+        let Some(stat) = self.stat() else {
+            return Handler::Default;
+        };
+        if let Some(source) = Self::source(&mut state.build, row) {
+            if flaw {
+                source.toggle_flaw(stat);
+            } else {
+                source.toggle_boost(stat);
+            }
+        } else if let Some(level) = Self::level(row)
+            && !flaw
+        {
+            state.build.toggle_free_boost(level, stat);
+        } else {
+            return Handler::Default;
+        }
+        state.rebuild_stats();
+        Handler::Consume
+    }
+
+    fn handle_rename(&self, state: &State, row: usize) -> Handler {
+        let Some(kind) = Self::SOURCES.get(row) else {
+            return Handler::Default;
+        };
+        let mut build = state.build.clone();
+        let name = Self::source(&mut build, row)
+            .map(|s| s.name.clone())
+            .unwrap_or_default();
+        Handler::Open(Box::new(editors::StringEditorModal::new(
+            kind,
+            name,
+            Box::new(move |name, state| {
+                if let Some(source) = Self::source(&mut state.build, row) {
+                    source.name = name;
+                }
+            }),
+        )))
+    }
+
+    fn handle_column(&self, forward: bool) -> Handler {
+        let column = self.column.get();
+        let column = if forward {
+            (column + 1).min(Stat::STATS.len())
+        } else {
+            column.saturating_sub(1)
+        };
+        self.column.set(column);
+        Handler::Consume
+    }
+}
+
+impl ElGroup<State> for BuildTable {
+    fn direction(&self) -> Direction {
+        Direction::Vertical
+    }
+
+    fn dimensions(&self, state: &State) -> Dims {
+        Dims::new(
+            Constraint::Min(16 + 4 * Stat::STATS.len() as u16 + BORDER),
+            Constraint::Length(
+                self.child_count(state) as u16
+                    + 2 // Header and scores
+                    + BORDER,
+            ),
+        )
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        state: &State,
+        selected: Option<usize>,
+    ) {
+        let build = &state.build;
+        let mut sources = [
+            build.ancestry.clone(),
+            build.background.clone(),
+            build.class.clone(),
+        ]
+        .into_iter();
+        let rows = (0..self.child_count(state)).map(|row| {
+            let (name, boosts, flaws, active) = match Self::level(row) {
+                Some(level) => (
+                    format!("Level {level}"),
+                    build.free_boosts(level).to_vec(),
+                    Vec::new(),
+                    level <= state.level.max(1),
+                ),
+                None => {
+                    let source = sources.next().unwrap_or_default();
+                    let name = if source.name.is_empty() {
+                        Self::SOURCES[row].to_string()
+                    } else {
+                        source.name
+                    };
+                    (name, source.boosts, source.flaws, true)
+                }
+            };
+            let cells =
+                std::iter::once(name).chain(Stat::STATS.iter().map(|stat| {
+                    let mut text = String::new();
+                    if boosts.contains(stat) {
+                        text.push('+');
+                    }
+                    if flaws.contains(stat) {
+                        text.push('-');
+                    }
+                    text
+                }));
+            let cells = cells.enumerate().map(|(column, text)| {
+                style_selected(
+                    Cell::new(text),
+                    selected == Some(row) && column == self.column.get(),
+                )
+            });
+            let row = Row::new(cells);
+            if active { row } else { row.dark_gray() }
+        });
+        let scores = Row::new(
+            std::iter::once("Score".to_string()).chain(
+                Stat::STATS
+                    .iter()
+                    .map(|&stat| state.stats.score(stat).to_string()),
+            ),
+        )
+        .bold();
+        let widths = std::iter::once(Constraint::Fill(1))
+            .chain(Stat::STATS.iter().map(|_| Constraint::Length(4)));
+        let table = Table::new(rows.chain(std::iter::once(scores)), widths)
+            .header(Row::new(
+                std::iter::once(String::new())
+                    .chain(Stat::STATS.iter().map(|s| s.short())),
+            ))
+            .block(
+                Block::bordered()
+                    .title("Build (Enter/b: boost, f: flaw, Esc: back)"),
+            );
+        frame.render_widget(table, area);
+    }
+
+    fn handle_key_press(
+        &self,
+        code: KeyCode,
+        state: &mut State,
+        selected: usize,
+    ) -> Handler {
+        match (code, view::Navigation::from_key_code(code)) {
+            (_, Some(view::Navigation::Left)) => self.handle_column(false),
+            (_, Some(view::Navigation::Right)) => self.handle_column(true),
+            (KeyCode::Enter, _) if self.column.get() == 0 => {
+                self.handle_rename(state, selected)
+            }
+            (KeyCode::Enter | KeyCode::Char('b'), _) => {
+                self.toggle(state, selected, false)
+            }
+            (KeyCode::Char('f'), _) => self.toggle(state, selected, true),
+            _ => Handler::Default,
+        }
+    }
+
+    fn child_count(&self, _state: &State) -> usize {
+        Self::SOURCES.len() + stats::Build::FREE_BOOST_LEVELS.len()
+    }
+
+    fn child_pos(
+        &self,
+        area: Rect,
+        _state: &State,
+        selected: usize,
+    ) -> (u16, u16) {
+        let x = area.x + area.width / 2;
+        let y = area.y + 1 + BORDER / 2 + selected as u16;
+        (x, y)
+    }
+
+    fn child_at_pos(
+        &self,
+        area: Rect,
+        state: &State,
+        _x: u16,
+        y: u16,
+    ) -> usize {
+        let y_offset = y.saturating_sub(area.y + 1 + BORDER / 2);
+        (y_offset as usize).min(self.child_count(state) - 1)
+    }
+}
+
+pub fn format_modifier(modifier: i64) -> String {
+    if modifier < 0 {
+        modifier.to_string()
+    } else {
+        format!("+{modifier}")
+    }
+}
+
+/// Style the provided widget based on a degree of success.
+pub fn style_degree<'a, T: 'a + Stylize<'a, T>>(
+    widget: T,
+    degree: roll::Degree,
+) -> T {
+    match degree {
+        roll::Degree::CriticalSuccess => widget.fg(Color::Green).bold(),
+        roll::Degree::Success => widget.fg(Color::Green),
+        roll::Degree::Failure => widget.fg(Color::Red),
+        roll::Degree::CriticalFailure => widget.fg(Color::Red).bold(),
+    }
+}
+
+pub struct RollDisplay {
+    dimensions: Dims,
+    roll_text: String,
+    result_text: String,
+    breakdown_text: String,
+    suppressed_text: String,
+    damage_text: String,
+    degree: Option<roll::Degree>,
+}
+
+impl RollDisplay {
+    pub fn new(outcome: &roll::RollOutcome) -> Self {
+        let roll_text = outcome.format_roll();
+        let result_text = format!(
+            "{} ({})",
+            outcome.format_value(),
+            outcome.format_results()
+        );
+        let breakdown_text = outcome.format_breakdown();
+        let suppressed_text = outcome.format_suppressed();
+        let damage_text = outcome.format_damage();
+        let degree = outcome.degree();
+        let degree_width = degree.map(|d| d.format().len()).unwrap_or(0);
+        let width = roll_text
+            .len()
+            .max(result_text.len())
+            .max(breakdown_text.len())
+            .max(suppressed_text.len())
+            .max(damage_text.len())
+            .max(degree_width);
+        let height = 2
+            + u16::from(!breakdown_text.is_empty())
+            + u16::from(!suppressed_text.is_empty())
+            + u16::from(!damage_text.is_empty())
+            + u16::from(degree.is_some());
+        let dimensions = Dims::length(width as u16, height);
+        Self {
+            dimensions,
+            roll_text,
+            result_text,
+            breakdown_text,
+            suppressed_text,
+            damage_text,
+            degree,
+        }
+    }
+}
+
+impl ElSimp<State> for RollDisplay {
+    fn dimensions(&self) -> Dims {
+        self.dimensions
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        _state: &State,
+        selected: bool,
+    ) {
+        let mut lines = vec![self.roll_text.to_line()];
+        if !self.breakdown_text.is_empty() {
+            lines.push(self.breakdown_text.to_line());
+        }
+        if !self.suppressed_text.is_empty() {
+            lines.push(self.suppressed_text.to_line().dark_gray());
+        }
+        lines.push(self.result_text.to_line());
+        if !self.damage_text.is_empty() {
+            lines.push(self.damage_text.to_line());
+        }
+        if let Some(degree) = self.degree {
+            lines.push(style_degree(Line::from(degree.format()), degree));
+        }
+        let widget = Paragraph::new(lines).centered();
+        frame.render_widget(style_selected(widget, selected), area);
+    }
+}
+
+pub struct Dice;
+
+impl Dice {
+    const DICE: &[u32] = &[4, 6, 8, 10, 12, 20];
+
+    fn iter_layout(
+        &self,
+        area: Rect,
+    ) -> impl Iterator<Item = (usize, Rect, String)> {
+        let mut labels: Vec<String> =
+            Self::DICE.iter().map(|d| format!("d{d}")).collect();
+        labels.push("Custom".to_string());
+        let areas = ratatui::prelude::Layout::new(
+            Direction::Horizontal,
+            vec![Constraint::Fill(1); Dice::DICE.len() + 1],
+        )
+        .split(area)
+        .to_vec();
+        labels
+            .into_iter()
+            .zip(areas)
+            .enumerate()
+            .map(|(i, (label, area))| (i, area, label))
+    }
+}
+
+impl ElGroup<State> for Dice {
+    fn dimensions(&self, _state: &State) -> Dims {
+        Dims::new(
+            Constraint::Min(4 * Dice::DICE.len() as u16 + BORDER),
+            Constraint::Length(BORDER + 1),
+        )
+    }
+
+    fn direction(&self) -> Direction {
+        Direction::Horizontal
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        _state: &State,
+        selected: Option<usize>,
+    ) {
+        for (i, area, label) in self.iter_layout(area) {
+            let area = if i == Dice::DICE.len() - 1 {
+                area
+            } else {
+                Rect::new(area.x, area.y, area.width + 1, area.height)
+            };
+            let widget = Paragraph::new(style_selected(
+                label.to_line(),
+                selected == Some(i),
+            ))
+            .block(Block::bordered());
+            frame.render_widget(widget, area);
+        }
+    }
+
+    fn child_count(&self, _state: &State) -> usize {
+        Dice::DICE.len() + 1
+    }
+
+    fn child_pos(
+        &self,
+        area: Rect,
+        _state: &State,
+        selected: usize,
+    ) -> (u16, u16) {
+        for (i, d_area, _) in self.iter_layout(area) {
+            if i == selected {
+                return centre_of(d_area);
+            }
+        }
+        centre_of(area)
+    }
+
+    fn child_at_pos(
+        &self,
+        area: Rect,
+        _state: &State,
+        x: u16,
+        y: u16,
+    ) -> usize {
+        for (i, d_area, _) in self.iter_layout(area) {
+            if d_area.contains(Position::new(x, y)) {
+                return i;
+            }
+        }
+        0
+    }
+
+    fn handle_roll(&self, state: &State, selected: usize) -> Handler {
+        if let Some(d) = Dice::DICE.get(selected).copied() {
+            let modal = editors::RollModal::new(Roll::new(1, d), state);
+            Handler::Open(Box::new(modal))
+        } else {
+            // Custom
+            Handler::Open(Box::new(editors::RollEditorModal::new()))
+        }
+    }
+
+    fn handle_odds(&self, state: &State, selected: usize) -> Handler {
+        if let Some(d) = Dice::DICE.get(selected).copied() {
+            let modal = editors::OddsModal::new(Roll::new(1, d), state);
+            Handler::Open(Box::new(modal))
+        } else {
+            Handler::Default
+        }
+    }
+
+    fn handle_select(&self, state: &State, selected: usize) -> Handler {
+        self.handle_roll(state, selected)
+    }
+}
+
+/// Conditions affecting the character, whose penalties are applied to
+/// rolls. Values are adjusted with + and -.
+pub struct ConditionsEl;
+
+impl ConditionsEl {
+    fn condition(
+        &self,
+        state: &State,
+        selected: usize,
+    ) -> Option<conditions::Condition> {
+        state.conditions.active().get(selected).map(|&(c, _)| c)
+    }
+
+    fn handle_increase(&self, state: &mut State, selected: usize) -> Handler {
+        match self.condition(state, selected) {
+            Some(condition) => {
+                state.conditions.increase(condition);
+                Handler::Consume
+            }
+            None => Handler::Default,
+        }
+    }
+
+    fn handle_decrease(&self, state: &mut State, selected: usize) -> Handler {
+        match self.condition(state, selected) {
+            Some(condition) => {
+                state.conditions.decrease(condition);
+                Handler::Consume
+            }
+            None => Handler::Default,
+        }
+    }
+
+    fn handle_delete(&self, state: &mut State, selected: usize) -> Handler {
+        match self.condition(state, selected) {
+            Some(condition) => {
+                state.conditions.remove(condition);
+                Handler::Consume
+            }
+            None => Handler::Default,
+        }
+    }
+}
+
+impl ElGroup<State> for ConditionsEl {
+    fn direction(&self) -> Direction {
+        Direction::Vertical
+    }
+
+    fn dimensions(&self, state: &State) -> Dims {
+        Dims::new(
+            Constraint::Fill(1),
+            Constraint::Length(self.child_count(state) as u16 + BORDER),
+        )
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        state: &State,
+        selected: Option<usize>,
+    ) {
+        let lines = state
+            .conditions
+            .active()
+            .iter()
+            .map(|(condition, value)| Line::from(condition.format(*value)))
+            .chain(std::iter::once(Line::from("+ Add condition")))
+            .enumerate()
+            .map(|(i, line)| style_selected(line, selected == Some(i)))
+            .collect::<Vec<Line>>();
+        let widget = Paragraph::new(lines).block(
+            Block::bordered().title("Conditions (+/-: value, e: end turn)"),
+        );
+        frame.render_widget(widget, area);
+    }
+
+    fn handle_key_press(
+        &self,
+        code: KeyCode,
+        state: &mut State,
+        selected: usize,
+    ) -> Handler {
+        match code {
+            KeyCode::Char('+') | KeyCode::Char('=') => {
+                self.handle_increase(state, selected)
+            }
+            KeyCode::Char('-') => self.handle_decrease(state, selected),
+            KeyCode::Char('e') => {
+                state.conditions.end_turn();
+                Handler::Consume
+            }
+            KeyCode::Delete => self.handle_delete(state, selected),
+            _ => self.handle_default_key_press(code, state, selected),
+        }
+    }
+
+    fn handle_select(&self, state: &State, selected: usize) -> Handler {
+        if self.condition(state, selected).is_none() {
+            Handler::Open(Box::new(editors::OptionModal::condition(state)))
+        } else {
+            Handler::Default
+        }
+    }
+
+    fn child_count(&self, state: &State) -> usize {
+        state.conditions.active().len() + 1
+    }
+
+    fn child_pos(
+        &self,
+        area: Rect,
+        _state: &State,
+        selected: usize,
+    ) -> (u16, u16) {
+        let x = area.x + area.width / 2;
+        let y = area.top() + selected as u16 + BORDER / 2;
+        (x, y)
+    }
+
+    fn child_at_pos(
+        &self,
+        area: Rect,
+        state: &State,
+        _x: u16,
+        y: u16,
+    ) -> usize {
+        let y_offset = y.saturating_sub(area.y + BORDER / 2);
+        (y_offset as usize).min(state.conditions.active().len())
+    }
+}
+
+/// Bonuses and penalties from effects on the character, which are applied
+/// to rolls by type.
+pub struct ModifiersEl;
+
+impl ModifiersEl {
+    fn handle_add(&self) -> Handler {
+        Handler::Open(Box::new(editors::ModifierModal::new(
+            "New modifier",
+            String::new(),
+            Box::new(|modifier, state| state.modifiers.push(modifier)),
+        )))
+    }
+
+    fn handle_delete(&self, state: &mut State, selected: usize) -> Handler {
+        if selected < state.modifiers.len() {
+            state.modifiers.remove(selected);
+            Handler::Consume
+        } else {
+            Handler::Default
+        }
+    }
+}
+
+impl ElGroup<State> for ModifiersEl {
+    fn direction(&self) -> Direction {
+        Direction::Vertical
+    }
+
+    fn dimensions(&self, state: &State) -> Dims {
+        Dims::new(
+            Constraint::Fill(1),
+            Constraint::Length(self.child_count(state) as u16 + BORDER),
+        )
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        state: &State,
+        selected: Option<usize>,
+    ) {
+        let rows = state
+            .modifiers
+            .iter()
+            .map(|m| {
+                Row::new([
+                    m.source.clone(),
+                    format_modifier(m.value),
+                    format!("{:?}", m.kind),
+                    m.selector.format(),
+                ])
+            })
+            .chain(std::iter::once(Row::new(["+ New modifier"])))
+            .enumerate()
+            .map(|(i, row)| style_selected(row, selected == Some(i)));
+        let widget = Table::new(
+            rows,
+            [
+                Constraint::Fill(1),
+                Constraint::Length(3),
+                Constraint::Length(12),
+                Constraint::Fill(1),
+            ],
+        )
+        .block(Block::bordered().title("Modifiers"));
+        frame.render_widget(widget, area);
+    }
+
+    fn handle_key_press(
+        &self,
+        code: KeyCode,
+        state: &mut State,
+        selected: usize,
+    ) -> Handler {
+        match code {
+            KeyCode::Delete => self.handle_delete(state, selected),
+            _ => self.handle_default_key_press(code, state, selected),
+        }
+    }
+
+    fn handle_select(&self, state: &State, selected: usize) -> Handler {
+        if let Some(modifier) = state.modifiers.get(selected) {
+            Handler::Open(Box::new(editors::ModifierModal::new(
+                "Edit modifier",
+                modifier.format(),
+                Box::new(move |modifier, state| {
+                    if let Some(existing) = state.modifiers.get_mut(selected) {
+                        *existing = modifier;
+                    }
+                }),
+            )))
+        } else {
+            self.handle_add()
+        }
+    }
+
+    fn child_count(&self, state: &State) -> usize {
+        state.modifiers.len() + 1
+    }
+
+    fn child_pos(
+        &self,
+        area: Rect,
+        _state: &State,
+        selected: usize,
+    ) -> (u16, u16) {
+        let x = area.x + area.width / 2;
+        let y = area.top() + selected as u16 + BORDER / 2;
+        (x, y)
+    }
+
+    fn child_at_pos(
+        &self,
+        area: Rect,
+        state: &State,
+        _x: u16,
+        y: u16,
+    ) -> usize {
+        let y_offset = y.saturating_sub(area.y + BORDER / 2);
+        (y_offset as usize).min(state.modifiers.len())
+    }
+}
+
+/// Table of the roll macros saved on the sheet, with a final row to add a
+/// new macro.
+pub struct MacrosEl;
+
+impl MacrosEl {
+    fn roll(&self, state: &State, selected: usize) -> Option<Roll> {
+        let roll_macro = state.macros.get(selected)?;
+        Roll::parse(&roll_macro.expression)
+            .ok()
+            .map(|roll| roll.labelled(&roll_macro.name))
+    }
+
+    /// Open a modal to name a new macro, which is then added to the sheet.
+    fn handle_add(&self) -> Handler {
+        Handler::Open(Box::new(editors::StringEditorModal::new(
+            "New macro",
+            String::new(),
+            Box::new(|name, state| {
+                state.macros.push(roll::RollMacro {
+                    name,
+                    expression: String::new(),
+                })
+            }),
+        )))
+    }
+
+    fn handle_rename(&self, state: &State, selected: usize) -> Handler {
+        if let Some(roll_macro) = state.macros.get(selected) {
+            Handler::Open(Box::new(editors::StringEditorModal::new(
+                "Rename macro",
+                roll_macro.name.clone(),
+                Box::new(move |name, state| {
+                    if let Some(roll_macro) = state.macros.get_mut(selected) {
+                        roll_macro.name = name;
+                    }
+                }),
+            )))
+        } else {
+            Handler::Default
+        }
+    }
+
+    /// Roll the selected macro as damage for a critical hit.
+    fn handle_critical(&self, state: &State, selected: usize) -> Handler {
+        if let Some(roll) = self.roll(state, selected) {
+            let roll = roll.critical(state.crit_rule);
+            Handler::Open(Box::new(editors::RollModal::new(roll, state)))
+        } else {
+            Handler::Default
+        }
+    }
+
+    fn handle_delete(&self, state: &mut State, selected: usize) -> Handler {
+        if selected < state.macros.len() {
+            state.macros.remove(selected);
+            Handler::Consume
+        } else {
+            Handler::Default
+        }
+    }
+}
+
+impl ElGroup<State> for MacrosEl {
+    fn direction(&self) -> Direction {
+        Direction::Vertical
+    }
+
+    fn dimensions(&self, state: &State) -> Dims {
+        Dims::new(
+            Constraint::Fill(1),
+            Constraint::Length(self.child_count(state) as u16 + BORDER),
+        )
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        state: &State,
+        selected: Option<usize>,
+    ) {
+        let rows = state
+            .macros
+            .iter()
+            .map(|m| Row::new([m.name.as_str(), m.expression.as_str()]))
+            .chain(std::iter::once(Row::new(["+ New macro", ""])))
+            .enumerate()
+            .map(|(i, row)| style_selected(row, selected == Some(i)));
+        let widget =
+            Table::new(rows, [Constraint::Fill(1), Constraint::Fill(2)])
+                .block(Block::bordered().title("Macros"));
+        frame.render_widget(widget, area);
+    }
+
+    fn handle_key_press(
+        &self,
+        code: KeyCode,
+        state: &mut State,
+        selected: usize,
+    ) -> Handler {
+        match code {
+            KeyCode::Char('x') => self.handle_critical(state, selected),
+            KeyCode::Char('n') => self.handle_rename(state, selected),
+            KeyCode::Delete => self.handle_delete(state, selected),
+            _ => self.handle_default_key_press(code, state, selected),
+        }
+    }
+
+    fn handle_select(&self, state: &State, selected: usize) -> Handler {
+        if let Some(roll_macro) = state.macros.get(selected) {
+            Handler::Open(Box::new(editors::RollEditorModal::editor(
+                &roll_macro.name,
+                roll_macro.expression.clone(),
+                Box::new(move |expression, state| {
+                    if let Some(roll_macro) = state.macros.get_mut(selected) {
+                        roll_macro.expression = expression;
+                    }
+                }),
+            )))
+        } else {
+            self.handle_add()
+        }
+    }
+
+    fn handle_roll(&self, state: &State, selected: usize) -> Handler {
+        if let Some(roll) = self.roll(state, selected) {
+            let modal = editors::RollModal::new(roll, state);
+            Handler::Open(Box::new(modal))
+        } else {
+            Handler::Default
+        }
+    }
+
+    fn handle_check(&self, state: &State, selected: usize) -> Handler {
+        if let Some(roll) = self.roll(state, selected) {
+            let modal = editors::CheckModal::new(roll, state);
+            Handler::Open(Box::new(modal))
+        } else {
+            Handler::Default
+        }
+    }
+
+    fn handle_odds(&self, state: &State, selected: usize) -> Handler {
+        if let Some(roll) = self.roll(state, selected) {
+            let modal = editors::OddsModal::new(roll, state);
+            Handler::Open(Box::new(modal))
+        } else {
+            Handler::Default
+        }
+    }
+
+    fn child_count(&self, state: &State) -> usize {
+        state.macros.len() + 1
+    }
+
+    fn child_pos(
+        &self,
+        area: Rect,
+        _state: &State,
+        selected: usize,
+    ) -> (u16, u16) {
+        let x = area.x + area.width / 2;
+        let y = area.top() + selected as u16 + BORDER / 2;
+        (x, y)
+    }
+
+    fn child_at_pos(
+        &self,
+        area: Rect,
+        state: &State,
+        _x: u16,
+        y: u16,
+    ) -> usize {
+        let y_offset = y.saturating_sub(area.y + BORDER / 2);
+        (y_offset as usize).min(state.macros.len())
+    }
+}
+
+/// Format the time elapsed since the provided time, e.g. 5m or 2d.
+fn format_age(time: std::time::SystemTime) -> String {
+    let seconds = time.elapsed().map(|d| d.as_secs()).unwrap_or(0);
+    match seconds {
+        0..60 => format!("{seconds}s"),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+pub struct RollHistory {
+    max_rolls_to_display: usize,
+
+    /// Page of the history being displayed, with 0 the most recent rolls.
+    page: std::cell::Cell<usize>,
+}
+
+impl RollHistory {
+    pub fn new(max_rolls_to_display: usize) -> Self {
+        Self {
             max_rolls_to_display,
+            page: std::cell::Cell::new(0),
+        }
+    }
+}
+
+impl RollHistory {
+    fn page_count(&self, state: &State) -> usize {
+        state.rolls.len().div_ceil(self.max_rolls_to_display).max(1)
+    }
+
+    /// Current page, limited to the pages available.
+    fn page(&self, state: &State) -> usize {
+        self.page.get().min(self.page_count(state) - 1)
+    }
+
+    /// Rolls on the current page, most recent first.
+    fn page_rolls<'a>(
+        &self,
+        state: &'a State,
+    ) -> impl Iterator<Item = &'a roll::RollOutcome> {
+        state
+            .rolls
+            .iter()
+            .rev()
+            .skip(self.page(state) * self.max_rolls_to_display)
+            .take(self.max_rolls_to_display)
+    }
+
+    fn selected_roll<'a>(
+        &self,
+        state: &'a State,
+        selected: usize,
+    ) -> Option<&'a roll::RollOutcome> {
+        self.page_rolls(state).nth(selected)
+    }
+
+    /// Move to an older page if forward is set, else a newer one.
+    fn handle_page(&self, state: &State, forward: bool) -> Handler {
+        let page = self.page(state);
+        let page = if forward {
+            (page + 1).min(self.page_count(state) - 1)
+        } else {
+            page.saturating_sub(1)
+        };
+        self.page.set(page);
+        Handler::Consume
+    }
+
+    /// Replay the selected roll from its position in the random stream and
+    /// report whether it matches the recorded results.
+    fn handle_audit(&self, state: &State, selected: usize) -> Handler {
+        if let Some(roll) = self.selected_roll(state, selected) {
+            let verdict = if roll.verify() {
+                "verified"
+            } else {
+                "DOES NOT MATCH"
+            };
+            let message = format!("{}: {verdict}", roll.format_source());
+            Handler::Open(Box::new(editors::MessageBox::new(message)))
+        } else {
+            Handler::Default
+        }
+    }
+
+    /// Write the whole roll history to a file in the data directory.
+    fn handle_export(&self, state: &State, format: roll::LogFormat) -> Handler {
+        let name = format!(
+            "rolls-{}.{}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|t| t.as_secs())
+                .unwrap_or_default(),
+            format.extension()
+        );
+        match roll::export_rolls(&state.rolls, format) {
+            Ok(data) => Handler::Open(Box::new(editors::export(&name, data))),
+            Err(e) => Handler::Open(Box::new(editors::MessageBox::new(
+                format!("Failed to export rolls: {e}"),
+            ))),
         }
     }
 }
@@ -458,7 +2101,7 @@ impl ElGroup<State> for RollHistory {
     }
 
     fn child_count(&self, state: &State) -> usize {
-        state.rolls.len().min(self.max_rolls_to_display)
+        self.page_rolls(state).count()
     }
 
     fn child_pos(
@@ -480,8 +2123,7 @@ impl ElGroup<State> for RollHistory {
         y: u16,
     ) -> usize {
         let y_offset = y.saturating_sub(area.y + 1 + BORDER / 2);
-        (y_offset as usize)
-            .min(state.rolls.len().min(self.max_rolls_to_display))
+        (y_offset as usize).min(self.child_count(state))
     }
 
     fn render(
@@ -491,41 +2133,224 @@ impl ElGroup<State> for RollHistory {
         state: &State,
         selected: Option<usize>,
     ) {
-        let rows = state
-            .rolls
-            .iter()
-            .rev()
-            .take(self.max_rolls_to_display)
-            .enumerate()
-            .map(|(i, oc)| {
-                let r = Row::new([
-                    oc.format_roll(),
-                    oc.format_results(),
-                    oc.format_value(),
-                ]);
-                style_selected(r, selected == Some(i))
-            });
+        let rows = self.page_rolls(state).enumerate().map(|(i, oc)| {
+            let check = match oc.degree() {
+                Some(degree) => {
+                    style_degree(Cell::new(degree.format()), degree)
+                }
+                None => Cell::default(),
+            };
+            let r = Row::new([
+                Cell::new(format_age(oc.timestamp())),
+                Cell::new(match oc.label() {
+                    Some(label) => format!("{label}: {}", oc.format_roll()),
+                    None => oc.format_roll(),
+                }),
+                Cell::new(oc.format_breakdown()),
+                Cell::new(oc.format_results()),
+                Cell::new(oc.format_value()),
+                Cell::new(oc.format_damage()),
+                check,
+            ]);
+            style_selected(r, selected == Some(i))
+        });
+        let mut block = Block::bordered()
+            .title("Rolls (s: stats, e: export CSV, E: export JSON)");
+        let pages = self.page_count(state);
+        if pages > 1 {
+            block = block.title(format!(
+                "Page {}/{pages} (PgUp/PgDn)",
+                self.page(state) + 1
+            ));
+        }
         let table = Table::default()
-            .header(Row::new(["Roll", "Results", "Total"]))
+            .header(Row::new([
+                "Age",
+                "Roll",
+                "Modifiers",
+                "Results",
+                "Total",
+                "Damage",
+                "Check",
+            ]))
+            .widths([
+                Constraint::Length(4),
+                Constraint::Fill(3),
+                Constraint::Fill(2),
+                Constraint::Fill(2),
+                Constraint::Fill(1),
+                Constraint::Fill(2),
+                Constraint::Fill(1),
+            ])
             .rows(rows)
-            .block(Block::bordered());
+            .block(block);
         frame.render_widget(table, area);
     }
 
+    fn handle_key_press(
+        &self,
+        code: KeyCode,
+        state: &mut State,
+        selected: usize,
+    ) -> Handler {
+        match code {
+            KeyCode::Char('a') => self.handle_audit(state, selected),
+            KeyCode::Char('s') => {
+                Handler::Open(Box::new(scenes::LuckScene::new()))
+            }
+            KeyCode::Char('e') => {
+                self.handle_export(state, roll::LogFormat::Csv)
+            }
+            KeyCode::Char('E') => {
+                self.handle_export(state, roll::LogFormat::Json)
+            }
+            KeyCode::PageDown => self.handle_page(state, true),
+            KeyCode::PageUp => self.handle_page(state, false),
+            _ => self.handle_default_key_press(code, state, selected),
+        }
+    }
+
     fn handle_select(&self, state: &State, selected: usize) -> Handler {
         self.handle_roll(state, selected)
     }
 
     fn handle_roll(&self, state: &State, selected: usize) -> Handler {
-        let index = state.rolls.len().saturating_sub(selected + 1);
-        if let Some(roll) = state.rolls.get(index) {
-            Handler::Open(Box::new(editors::RollModal::new(roll.clone_roll())))
+        if let Some(roll) = self.selected_roll(state, selected) {
+            let modal = editors::RollModal::new(roll.clone_roll(), state);
+            Handler::Open(Box::new(modal))
+        } else {
+            Handler::Default
+        }
+    }
+
+    fn handle_check(&self, state: &State, selected: usize) -> Handler {
+        if let Some(roll) = self.selected_roll(state, selected) {
+            let modal = editors::CheckModal::new(roll.clone_roll(), state);
+            Handler::Open(Box::new(modal))
+        } else {
+            Handler::Default
+        }
+    }
+
+    fn handle_odds(&self, state: &State, selected: usize) -> Handler {
+        if let Some(roll) = self.selected_roll(state, selected) {
+            let modal = editors::OddsModal::new(roll.clone_roll(), state);
+            Handler::Open(Box::new(modal))
         } else {
             Handler::Default
         }
     }
 }
 
+/// Histogram of the natural results rolled on d20s across the roll history.
+pub struct D20Frequencies;
+
+impl D20Frequencies {
+    const SIZE: u32 = 20;
+}
+
+impl ElSimp<State> for D20Frequencies {
+    fn dimensions(&self) -> Dims {
+        Dims::new(
+            Constraint::Fill(1),
+            Constraint::Length(Self::SIZE as u16 + BORDER),
+        )
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        state: &State,
+        selected: bool,
+    ) {
+        let stats = roll::LuckStats::new(&state.rolls);
+        let counts = stats
+            .die(Self::SIZE)
+            .map(|d| d.counts().to_vec())
+            .unwrap_or_else(|| vec![0; Self::SIZE as usize]);
+        let most = counts.iter().copied().max().unwrap_or(0);
+        let count_width = most.to_string().len();
+        // Leave room for the face, count and spaces between them.
+        let bar_width = (area.width.saturating_sub(BORDER) as usize)
+            .saturating_sub(count_width + 4)
+            .max(1);
+        let lines: Vec<Line> = (1..)
+            .zip(&counts)
+            .map(|(face, &n)| {
+                let length = if most > 0 {
+                    (n as f64 / most as f64 * bar_width as f64).round() as usize
+                } else {
+                    0
+                };
+                Line::from(format!(
+                    "{face:>2} {:<bar_width$} {n:>count_width$}",
+                    "#".repeat(length)
+                ))
+            })
+            .collect();
+        let title = format!(
+            "d20 results: {} nat 20s, {} nat 1s",
+            stats.nat_20s(),
+            stats.nat_1s()
+        );
+        frame.render_widget(
+            style_selected(
+                Paragraph::new(lines).block(Block::bordered().title(title)),
+                selected,
+            ),
+            area,
+        );
+    }
+}
+
+/// Table comparing the results of each size of die rolled against a fair
+/// die.
+pub struct DiceFairness;
+
+impl ElSimp<State> for DiceFairness {
+    fn dimensions(&self) -> Dims {
+        Dims::new(Constraint::Fill(1), Constraint::Fill(1))
+    }
+
+    fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        state: &State,
+        selected: bool,
+    ) {
+        let stats = roll::LuckStats::new(&state.rolls);
+        let rows = stats.dice().iter().map(|die| {
+            Row::new([
+                format!("d{}", die.size()),
+                die.total().to_string(),
+                format!("{:.2}", die.average()),
+                format!("{:.2}", die.expected()),
+                format!("{:.2}", die.chi_squared()),
+                format!("{:.3}", die.p_value()),
+                die.verdict().to_string(),
+            ])
+        });
+        let table = Table::default()
+            .header(Row::new([
+                "Die", "Rolled", "Average", "Expected", "Chi²", "p", "Verdict",
+            ]))
+            .widths([
+                Constraint::Length(5),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Fill(2),
+            ])
+            .rows(rows)
+            .block(Block::bordered().title("Fairness (e: export as text)"));
+        frame.render_widget(style_selected(table, selected), area);
+    }
+}
+
 pub struct SpellbookStatus;
 
 impl ElSimp<SheetState> for SpellbookStatus {
@@ -7,12 +7,17 @@ fn data_directory() -> std::path::PathBuf {
     app_directory().join("data")
 }
 
+/// Path of the named file in the data directory.
+pub fn data_path(name: &str) -> std::path::PathBuf {
+    data_directory().join(name)
+}
+
 pub fn read_data(name: &str) -> std::io::Result<impl std::io::Read> {
-    std::fs::File::open(data_directory().join(name))
+    std::fs::File::open(data_path(name))
 }
 
 pub fn write_data(name: &str, data: impl AsRef<[u8]>) -> std::io::Result<()> {
     let dir = data_directory();
     std::fs::create_dir_all(&dir)?;
-    std::fs::write(dir.join(name), data)
+    std::fs::write(data_path(name), data)
 }
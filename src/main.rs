@@ -6,9 +6,11 @@ use ratatui::{
     prelude::*,
 };
 
+mod conditions;
 mod editors;
 mod els;
 mod fs;
+mod modifiers;
 mod roll;
 mod scenes;
 mod spells;
@@ -17,23 +19,196 @@ mod view;
 
 const APP_NAME: &str = "chshtui";
 
+/// Number of rolls kept in the history by default.
+const DEFAULT_ROLL_RETENTION: usize = 500;
+
 #[derive(Default, serde::Deserialize, serde::Serialize)]
 struct SheetState {
     name: String,
     level: i64,
     stats: stats::Stats,
+
+    /// Choices the ability scores in stats are calculated from.
+    #[serde(default)]
+    build: stats::Build,
     skills: stats::Skills,
 
+    #[serde(default)]
+    perception: stats::Perception,
+
+    /// Feats taken, in the order their slots were gained.
+    #[serde(default)]
+    feats: Vec<stats::Feat>,
+
+    /// Result of the most recent initiative roll.
+    #[serde(default)]
+    initiative: Option<i64>,
+
+    #[serde(default)]
+    saves: stats::Saves,
+
+    #[serde(default)]
+    ac: stats::ArmorClass,
+
+    #[serde(default)]
+    hp: stats::HitPoints,
+
+    #[serde(default)]
+    death: stats::DeathConditions,
+
+    #[serde(default)]
+    conditions: conditions::Conditions,
+
     #[serde(skip)]
     spellbook: spells::SpellBook,
 
-    #[serde(skip)]
+    /// Typed bonuses and penalties from effects on the character.
+    #[serde(default)]
+    modifiers: Vec<modifiers::Modifier>,
+
+    /// Named roll expressions saved for reuse.
+    #[serde(default)]
+    macros: Vec<roll::RollMacro>,
+
+    /// Rule for increasing damage on a critical hit.
+    #[serde(default)]
+    crit_rule: roll::CritRule,
+
+    /// History of rolls made, oldest first.
+    #[serde(default)]
     rolls: Vec<roll::RollOutcome>,
 
+    #[serde(skip)]
+    dice: roll::DiceRng,
+
+    /// Defenses of the target of the most recent damage roll, used as the
+    /// default for the next.
+    #[serde(skip)]
+    target_defenses: String,
+
+    /// DC of the most recent check, used as the default for the next.
+    #[serde(skip)]
+    last_dc: Option<i64>,
+
     #[serde(skip)]
     window_dimensions: ratatui::layout::Rect,
 }
 
+impl SheetState {
+    /// Look up the value of a variable referenced in a roll, e.g. dex, level,
+    /// ac, perception, skill.Stealth or save.Will. Names are case insensitive
+    /// and underscores may be used in place of spaces.
+    fn variable(&self, name: &str) -> Option<i64> {
+        let name = name.to_lowercase().replace('_', " ");
+        if name == "level" {
+            return Some(self.level);
+        }
+        if name == "ac" {
+            return Some(self.ac.value(self));
+        }
+        // Perception was once a skill, so macros may refer to it as one.
+        if name == "perception" || name == "skill.perception" {
+            return Some(self.perception.modifier(self));
+        }
+
+        if let Some(skill) = name.strip_prefix("skill.") {
+            return self
+                .skills
+                .0
+                .iter()
+                .find(|s| s.name.to_lowercase() == skill)
+                .map(|s| s.modifier(self));
+        }
+
+        if let Some(save) = name.strip_prefix("save.") {
+            return stats::Save::SAVES
+                .iter()
+                .find(|s| format!("{s:?}").to_lowercase() == save)
+                .map(|&s| self.saves.modifier(s, self));
+        }
+
+        stats::Stat::STATS
+            .iter()
+            .find(|stat| {
+                stat.short().to_lowercase() == name
+                    || format!("{stat:?}").to_lowercase() == name
+            })
+            .map(|&stat| self.stats.modifier(stat))
+    }
+
+    /// Modifiers from effects and conditions on the character.
+    fn active_modifiers(&self) -> Vec<modifiers::Modifier> {
+        let mut modifiers = self.modifiers.clone();
+        modifiers.extend(self.conditions.modifiers());
+        modifiers
+    }
+
+    /// Determine which modifiers from effects and conditions apply to a roll
+    /// or value.
+    fn resolve_modifiers(
+        &self,
+        target: modifiers::Target,
+    ) -> modifiers::Resolution {
+        modifiers::Resolution::new(&self.active_modifiers(), target)
+    }
+
+    /// Recalculate the ability scores from the build.
+    fn rebuild_stats(&mut self) {
+        self.stats = self.build.stats(self.level);
+    }
+
+    /// Keep the scores of sheets saved before builds were tracked, by
+    /// recording them as manual adjustments.
+    fn migrate_stats(&mut self) {
+        if self.build.is_empty() {
+            for &stat in stats::Stat::STATS {
+                let adjustment = self.stats.score(stat) - 10;
+                self.build.set_adjustment(stat, adjustment);
+            }
+        }
+        self.rebuild_stats();
+    }
+
+    /// Move perception out of the skills of sheets saved before it was
+    /// tracked separately.
+    fn migrate_perception(&mut self) {
+        if let Some(skill) = self.skills.remove(stats::Perception::NAME) {
+            self.perception.proficiency = skill.proficiency;
+        }
+    }
+
+    /// Discard the oldest rolls in the history, keeping at most limit.
+    fn trim_rolls(&mut self, limit: usize) {
+        let excess = self.rolls.len().saturating_sub(limit);
+        self.rolls.drain(..excess);
+    }
+
+    /// Take damage, falling unconscious and dying if it reduces the
+    /// character to 0 HP.
+    fn take_damage(&mut self, amount: i64, critical: bool) {
+        let mut hp = self.hp.clone();
+        let absorbed = hp.temp;
+        hp.take_damage(amount, self);
+        self.hp = hp;
+        if amount > absorbed && self.hp.current(self) == 0 {
+            self.death.knocked_out(critical);
+        }
+    }
+
+    /// Recover HP, which ends dying if the character was dying.
+    fn heal(&mut self, amount: i64) {
+        self.hp.heal(amount);
+        if self.hp.current(self) > 0 {
+            self.death.revive();
+        }
+    }
+
+    /// Resolve the sheet variables in a roll against current values.
+    fn bind_roll(&self, roll: roll::Roll) -> Result<roll::Roll, String> {
+        roll.bind(|name| self.variable(name))
+    }
+}
+
 /// Handler for an input event.
 type Handler = view::HandleResult<SheetState>;
 
@@ -183,12 +358,33 @@ impl App {
 }
 
 fn main() -> std::io::Result<()> {
-    let save_file = match std::env::args().nth(1) {
-        Some(path) => path.to_string(),
-        None => "character.json".to_string(),
-    };
+    let mut save_file = "character.json".to_string();
+    let mut seed = None;
+    let mut retention = DEFAULT_ROLL_RETENTION;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--seed" {
+            match args.next().and_then(|s| s.parse::<u64>().ok()) {
+                Some(value) => seed = Some(value),
+                None => {
+                    eprintln!("--seed requires a non-negative integer");
+                    std::process::exit(1);
+                }
+            }
+        } else if arg == "--keep-rolls" {
+            match args.next().and_then(|s| s.parse::<usize>().ok()) {
+                Some(value) => retention = value,
+                None => {
+                    eprintln!("--keep-rolls requires a non-negative integer");
+                    std::process::exit(1);
+                }
+            }
+        } else {
+            save_file = arg;
+        }
+    }
 
-    let state = match std::fs::File::open(&save_file) {
+    let mut state = match std::fs::File::open(&save_file) {
         Ok(file) => match serde_json::de::from_reader(file) {
             Ok(state) => state,
             Err(e) => {
@@ -205,6 +401,13 @@ fn main() -> std::io::Result<()> {
         }
     };
 
+    if let Some(seed) = seed {
+        state.dice = roll::DiceRng::seeded(seed);
+    }
+    state.migrate_perception();
+    state.migrate_stats();
+    state.trim_rolls(retention);
+
     let mut app = App::new(state);
 
     let mut term = ratatui::init();
@@ -213,6 +416,7 @@ fn main() -> std::io::Result<()> {
     ratatui::restore();
     crossterm::execute!(std::io::stdout(), crossterm::cursor::Show).ok();
 
+    app.state.trim_rolls(retention);
     match serde_json::ser::to_string(&app.state) {
         Ok(json) => match std::fs::write(&save_file, json) {
             Ok(_) => println!("saved to {save_file}"),
@@ -0,0 +1,392 @@
+use crate::{
+    roll,
+    stats::{Save, Skills, Stat},
+};
+
+/// Type of a bonus or penalty. Only the largest bonus and the worst penalty
+/// of each type apply, except for untyped penalties which all stack.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+pub enum ModifierKind {
+    Circumstance,
+    Status,
+    Item,
+    Untyped,
+}
+
+impl ModifierKind {
+    pub const KINDS: &[ModifierKind] = &[
+        ModifierKind::Circumstance,
+        ModifierKind::Status,
+        ModifierKind::Item,
+        ModifierKind::Untyped,
+    ];
+
+    fn name(&self) -> String {
+        format!("{self:?}").to_lowercase()
+    }
+}
+
+/// Roll or value which a modifier may apply to.
+#[derive(Clone, Copy, Debug)]
+pub enum Target<'a> {
+    /// A check using only a stat.
+    Stat(Stat),
+    Skill {
+        name: &'a str,
+        stat: Stat,
+    },
+    Perception,
+    Save(Save),
+    Ac,
+}
+
+impl Target<'_> {
+    /// Stat this roll or value is based on.
+    fn stat(&self) -> Stat {
+        match self {
+            Target::Stat(stat) | Target::Skill { stat, .. } => *stat,
+            Target::Perception => Stat::Wisdom,
+            Target::Save(save) => save.stat(),
+            Target::Ac => Stat::Dexterity,
+        }
+    }
+}
+
+/// What a modifier applies to, e.g. all skills, DEX-based rolls or Stealth.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Selector {
+    /// Every check and AC.
+    All,
+    Skills,
+    Saves,
+    Perception,
+    Ac,
+    /// Every check or value based on a stat.
+    Stat(Stat),
+    Save(Save),
+    /// A single skill, by name.
+    Skill(String),
+}
+
+impl Selector {
+    /// Parse a selector, e.g. all, skills, saves, perception, ac, dex-based,
+    /// reflex or a skill name such as Stealth.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let lower = text.to_lowercase();
+        let stat_name = lower.strip_suffix("-based").unwrap_or(&lower);
+        let selector = match lower.as_str() {
+            "" => return Err("Expected what the modifier applies to.".into()),
+            "all" => Selector::All,
+            "skills" => Selector::Skills,
+            "saves" => Selector::Saves,
+            "perception" => Selector::Perception,
+            "ac" => Selector::Ac,
+            _ => {
+                if let Some(&stat) = Stat::STATS.iter().find(|s| {
+                    s.short().to_lowercase() == stat_name
+                        || format!("{s:?}").to_lowercase() == stat_name
+                }) {
+                    Selector::Stat(stat)
+                } else if let Some(&save) = Save::SAVES.iter().find(|s| {
+                    s.short().to_lowercase() == lower
+                        || format!("{s:?}").to_lowercase() == lower
+                }) {
+                    Selector::Save(save)
+                } else {
+                    Selector::Skill(text.to_string())
+                }
+            }
+        };
+        Ok(selector)
+    }
+
+    pub fn format(&self) -> String {
+        match self {
+            Selector::All => "all".to_string(),
+            Selector::Skills => "skills".to_string(),
+            Selector::Saves => "saves".to_string(),
+            Selector::Perception => "perception".to_string(),
+            Selector::Ac => "AC".to_string(),
+            Selector::Stat(stat) => format!("{}-based", stat.short()),
+            Selector::Save(save) => format!("{save:?}"),
+            Selector::Skill(name) => name.clone(),
+        }
+    }
+
+    pub fn matches(&self, target: &Target) -> bool {
+        match (self, target) {
+            (Selector::All, _) => true,
+            (Selector::Skills, Target::Skill { .. }) => true,
+            (Selector::Saves, Target::Save(_)) => true,
+            (Selector::Perception, Target::Perception) => true,
+            (Selector::Ac, Target::Ac) => true,
+            (Selector::Stat(stat), target) => *stat == target.stat(),
+            (Selector::Save(save), Target::Save(target)) => save == target,
+            (Selector::Skill(skill), Target::Skill { name, .. }) => {
+                skill.eq_ignore_ascii_case(name)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A bonus or penalty from an effect on the character.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Modifier {
+    /// Effect this modifier comes from, e.g. Heroism.
+    pub source: String,
+    pub kind: ModifierKind,
+    pub value: i64,
+    pub selector: Selector,
+}
+
+impl Modifier {
+    /// Parse a modifier written as source: value [type] selector, e.g.
+    /// "Heroism: +1 status all" or "Cover: +2 circumstance AC". Modifiers
+    /// without a type are untyped.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let Some((source, rest)) = text.split_once(':') else {
+            return Err("Expected source: value [type] selector.".into());
+        };
+        let source = source.trim();
+        if source.is_empty() {
+            return Err("Expected the source of the modifier.".into());
+        }
+
+        let rest = rest.trim_start();
+        let (value, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+        let value = value
+            .trim_start_matches('+')
+            .parse::<i64>()
+            .map_err(|_| format!("Invalid value: {value}"))?;
+
+        let rest = rest.trim_start();
+        let (word, selector) = rest.split_once(' ').unwrap_or((rest, ""));
+        let (kind, selector) = match ModifierKind::KINDS
+            .iter()
+            .find(|k| k.name() == word.to_lowercase())
+        {
+            Some(&kind) => (kind, selector),
+            None => (ModifierKind::Untyped, rest),
+        };
+
+        Ok(Self {
+            source: source.to_string(),
+            kind,
+            value,
+            selector: Selector::parse(selector)?,
+        })
+    }
+
+    /// Check that a modifier to a single skill names one the character has,
+    /// as any unrecognised selector is taken to be a skill name.
+    pub fn check(&self, skills: &Skills) -> Result<(), String> {
+        match &self.selector {
+            Selector::Skill(name)
+                if !skills
+                    .0
+                    .iter()
+                    .any(|s| s.name.eq_ignore_ascii_case(name)) =>
+            {
+                Err(format!("Unknown skill: {name}"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn format(&self) -> String {
+        let kind = match self.kind {
+            ModifierKind::Untyped => String::new(),
+            kind => format!("{} ", kind.name()),
+        };
+        format!(
+            "{}: {:+} {kind}{}",
+            self.source,
+            self.value,
+            self.selector.format()
+        )
+    }
+}
+
+/// Modifiers which apply to a roll or value, split into those which count
+/// and those suppressed by a larger modifier of the same type.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Resolution {
+    pub applied: Vec<Modifier>,
+    pub suppressed: Vec<Modifier>,
+}
+
+impl Resolution {
+    /// Determine which of the modifiers matching the target apply.
+    pub fn new<'a>(
+        modifiers: impl IntoIterator<Item = &'a Modifier>,
+        target: Target,
+    ) -> Self {
+        let matching: Vec<&Modifier> = modifiers
+            .into_iter()
+            .filter(|m| m.selector.matches(&target))
+            .collect();
+
+        // Index of the modifier which applies for each typed bonus and
+        // penalty.
+        let mut best: Vec<(ModifierKind, bool, usize)> = Vec::new();
+        for (i, modifier) in matching.iter().enumerate() {
+            if modifier.kind == ModifierKind::Untyped {
+                continue;
+            }
+            let bonus = modifier.value >= 0;
+            match best
+                .iter_mut()
+                .find(|(k, b, _)| *k == modifier.kind && *b == bonus)
+            {
+                Some((_, _, j)) => {
+                    if modifier.value.abs() > matching[*j].value.abs() {
+                        *j = i;
+                    }
+                }
+                None => best.push((modifier.kind, bonus, i)),
+            }
+        }
+
+        let mut resolution = Self::default();
+        for (i, modifier) in matching.into_iter().enumerate() {
+            if modifier.kind == ModifierKind::Untyped
+                || best.iter().any(|(_, _, j)| *j == i)
+            {
+                resolution.applied.push(modifier.clone());
+            } else {
+                resolution.suppressed.push(modifier.clone());
+            }
+        }
+        resolution
+    }
+
+    pub fn total(&self) -> i64 {
+        self.applied.iter().map(|m| m.value).sum()
+    }
+
+    /// Add the applied modifiers to a roll, labelled with their sources, and
+    /// record those suppressed.
+    pub fn apply(&self, roll: roll::Roll) -> roll::Roll {
+        let roll = self.applied.iter().fold(roll, |roll, m| {
+            roll.plus_labelled(m.value as f64, &m.source)
+        });
+        roll.suppressing(self.suppressed.iter().map(Modifier::format).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn modifier(text: &str) -> Modifier {
+        Modifier::parse(text).unwrap()
+    }
+
+    #[test]
+    fn test_parse_modifier() {
+        assert_eq!(
+            modifier("Heroism: +1 status all"),
+            Modifier {
+                source: "Heroism".to_string(),
+                kind: ModifierKind::Status,
+                value: 1,
+                selector: Selector::All,
+            }
+        );
+        assert_eq!(
+            modifier("Drained: -2 CON-based").selector,
+            Selector::Stat(Stat::Constitution)
+        );
+        assert_eq!(
+            modifier("Cloak: +1 item Warfare Lore").selector,
+            Selector::Skill("Warfare Lore".to_string())
+        );
+        assert_eq!(
+            modifier("Cover: +2 circumstance ac").selector,
+            Selector::Ac
+        );
+        assert_eq!(
+            modifier("Ward: +1 status reflex").selector,
+            Selector::Save(Save::Reflex)
+        );
+        assert!(Modifier::parse("+1 status all").is_err());
+        assert!(Modifier::parse("Heroism: one status all").is_err());
+        assert!(Modifier::parse("Heroism: +1 status").is_err());
+
+        let skills = crate::SheetState::default().skills;
+        assert!(modifier("Cloak: +1 item stealth").check(&skills).is_ok());
+        assert!(modifier("Cloak: +1 item Stealht").check(&skills).is_err());
+        assert!(modifier("Heroism: +1 status all").check(&skills).is_ok());
+
+        let text = "Cover: +2 circumstance AC";
+        assert_eq!(modifier(text).format(), text);
+        assert_eq!(modifier("Pit: -1 DEX").format(), "Pit: -1 DEX-based");
+    }
+
+    #[test]
+    fn test_selector_matches() {
+        let stealth = Target::Skill {
+            name: "Stealth",
+            stat: Stat::Dexterity,
+        };
+        assert!(Selector::All.matches(&Target::Ac));
+        assert!(Selector::Skills.matches(&stealth));
+        assert!(!Selector::Skills.matches(&Target::Perception));
+        assert!(Selector::Stat(Stat::Dexterity).matches(&stealth));
+        assert!(Selector::Stat(Stat::Dexterity).matches(&Target::Ac));
+        assert!(
+            Selector::Stat(Stat::Dexterity)
+                .matches(&Target::Save(Save::Reflex))
+        );
+        assert!(!Selector::Stat(Stat::Wisdom).matches(&stealth));
+        assert!(Selector::Skill("stealth".to_string()).matches(&stealth));
+        assert!(Selector::Save(Save::Will).matches(&Target::Save(Save::Will)));
+        assert!(!Selector::Saves.matches(&Target::Stat(Stat::Wisdom)));
+    }
+
+    #[test]
+    fn test_resolution() {
+        let modifiers = [
+            modifier("Heroism: +1 status all"),
+            modifier("Inspire: +2 status skills"),
+            modifier("Aid: +2 circumstance Stealth"),
+            modifier("Cloak: +1 item Stealth"),
+            modifier("Frightened: -2 status all"),
+            modifier("Sickened: -1 status all"),
+            modifier("Pit: -1 Stealth"),
+            modifier("Mud: -1 DEX"),
+            modifier("Ward: +1 status saves"),
+        ];
+        let stealth = Target::Skill {
+            name: "Stealth",
+            stat: Stat::Dexterity,
+        };
+        let resolution = Resolution::new(&modifiers, stealth);
+        let sources = |mods: &[Modifier]| {
+            mods.iter()
+                .map(|m| m.source.clone())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            sources(&resolution.applied),
+            ["Inspire", "Aid", "Cloak", "Frightened", "Pit", "Mud"]
+        );
+        assert_eq!(sources(&resolution.suppressed), ["Heroism", "Sickened"]);
+        assert_eq!(resolution.total(), 2 + 2 + 1 - 2 - 1 - 1);
+
+        let will = Resolution::new(&modifiers, Target::Save(Save::Will));
+        assert_eq!(sources(&will.applied), ["Heroism", "Frightened"]);
+        assert_eq!(sources(&will.suppressed), ["Sickened", "Ward"]);
+        assert_eq!(will.total(), -1);
+
+        let roll = resolution.apply(roll::Roll::new(1, 20));
+        assert_eq!(
+            roll.format_suppressed(),
+            "Suppressed: Heroism: +1 status all, Sickened: -1 status all"
+        );
+    }
+}
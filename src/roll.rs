@@ -1,3 +1,58 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::{Rng, SeedableRng};
+
+/// Source of random numbers for dice rolls. All rolls are drawn from a single
+/// seeded stream, so any roll can be reproduced exactly from the seed and its
+/// position in the stream.
+pub struct DiceRng {
+    seed: u64,
+    rng: std::cell::RefCell<rand_chacha::ChaCha8Rng>,
+}
+
+impl DiceRng {
+    pub fn seeded(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: std::cell::RefCell::new(
+                rand_chacha::ChaCha8Rng::seed_from_u64(seed),
+            ),
+        }
+    }
+
+    /// Create a source positioned part way through the stream of the
+    /// provided seed.
+    fn at(seed: u64, position: u128) -> Self {
+        let rng = Self::seeded(seed);
+        rng.rng.borrow_mut().set_word_pos(position);
+        rng
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn position(&self) -> u128 {
+        self.rng.borrow().get_word_pos()
+    }
+
+    /// Roll a single die of the provided size.
+    fn roll(&self, size: u32) -> u32 {
+        self.rng.borrow_mut().random_range(1..=size.max(1))
+    }
+}
+
+impl Default for DiceRng {
+    fn default() -> Self {
+        Self::seeded(rand::random())
+    }
+}
+
+/// Limit on the number of times a single die may be rerolled or explode, to
+/// avoid rolling forever when every result triggers a reroll or explosion.
+const MAX_REPEATS: usize = 100;
+
+/// Rule selecting which dice in a group count towards its total.
 #[derive(
     Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize,
 )]
@@ -6,6 +61,9 @@ enum RollSuff {
     Advantage,
     Disadvantage,
     Keep(u32),
+    KeepLowest(u32),
+    DropHighest(u32),
+    DropLowest(u32),
 }
 
 impl RollSuff {
@@ -15,6 +73,111 @@ impl RollSuff {
             Self::Advantage => "a".to_string(),
             Self::Disadvantage => "d".to_string(),
             Self::Keep(n) => format!("k{n}"),
+            Self::KeepLowest(n) => format!("kl{n}"),
+            Self::DropHighest(n) => format!("dh{n}"),
+            Self::DropLowest(n) => format!("dl{n}"),
+        }
+    }
+
+    /// Return whether the highest (true) or lowest (false) dice are kept out
+    /// of a group of count dice and how many of them, or None if all dice are
+    /// kept.
+    fn keep_rule(&self, count: u32) -> Option<(bool, u32)> {
+        let (highest, n) = match *self {
+            Self::None => return None,
+            Self::Advantage => (true, 1),
+            Self::Disadvantage => (false, 1),
+            Self::Keep(n) => (true, n),
+            Self::KeepLowest(n) => (false, n),
+            Self::DropHighest(n) => (false, count.saturating_sub(n)),
+            Self::DropLowest(n) => (true, count.saturating_sub(n)),
+        };
+        Some((highest, n.min(count)))
+    }
+}
+
+/// Comparison of a die result against a target number.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+enum Compare {
+    Eq(u32),
+    Lt(u32),
+    Le(u32),
+    Gt(u32),
+    Ge(u32),
+}
+
+impl Compare {
+    fn matches(&self, value: u32) -> bool {
+        match *self {
+            Self::Eq(n) => value == n,
+            Self::Lt(n) => value < n,
+            Self::Le(n) => value <= n,
+            Self::Gt(n) => value > n,
+            Self::Ge(n) => value >= n,
+        }
+    }
+
+    fn format(&self) -> String {
+        match self {
+            Self::Eq(n) => n.to_string(),
+            Self::Lt(n) => format!("<{n}"),
+            Self::Le(n) => format!("<={n}"),
+            Self::Gt(n) => format!(">{n}"),
+            Self::Ge(n) => format!(">={n}"),
+        }
+    }
+}
+
+/// Rule for rerolling dice whose results match a comparison, either once or
+/// until the result no longer matches.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+struct Reroll {
+    once: bool,
+    when: Compare,
+}
+
+impl Reroll {
+    fn format(&self) -> String {
+        let prefix = if self.once { "r" } else { "rr" };
+        format!("{prefix}{}", self.when.format())
+    }
+}
+
+/// Rule for counting successes in a pool of dice instead of summing them,
+/// optionally subtracting a failure for each die matching another rule.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+struct Count {
+    success: Compare,
+    failure: Option<Compare>,
+}
+
+impl Count {
+    /// Number of successes a single die result is worth.
+    fn score(&self, value: u32) -> i64 {
+        if self.success.matches(value) {
+            1
+        } else if self.failure.is_some_and(|f| f.matches(value)) {
+            -1
+        } else {
+            0
+        }
+    }
+
+    fn format(&self) -> String {
+        // A bare number would be read as part of the die size.
+        let success = match self.success {
+            Compare::Eq(n) => format!("={n}"),
+            compare => compare.format(),
+        };
+        match self.failure {
+            Some(failure) => format!("{success}f{}", failure.format()),
+            None => success,
         }
     }
 }
@@ -47,331 +210,2286 @@ impl RollOp {
             Self::Div => "/",
         }
     }
+
+    /// Binding strength of this operator. Operators with higher precedence
+    /// are applied first.
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::Add | Self::Sub => 1,
+            Self::Mul | Self::Div => 2,
+        }
+    }
 }
 
+/// An operation applied to the result of a roll expression, with the operand
+/// being another expression.
 #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 struct RollMod {
     op: RollOp,
-    amount: f64,
+    operand: Box<Expr>,
+
+    /// Source of this modifier, e.g. DEX or Trained, shown in breakdowns.
+    #[serde(default)]
+    label: Option<String>,
 }
 
 impl RollMod {
-    fn apply(&self, to: f64) -> f64 {
+    fn new(op: RollOp, operand: Expr) -> Self {
+        Self {
+            op,
+            operand: Box::new(operand),
+            label: None,
+        }
+    }
+
+    /// Labelled amount this modifier adds, if it has a label and adds or
+    /// subtracts a fixed amount.
+    fn contribution(&self) -> Option<(String, f64)> {
+        let label = self.label.clone()?;
+        let distribution = self.operand.distribution().ok()?;
+        if distribution.min() != distribution.max() {
+            return None;
+        }
+
         match self.op {
-            RollOp::Add => to + self.amount,
-            RollOp::Sub => to - self.amount,
-            RollOp::Mul => to * self.amount,
-            RollOp::Div => to / self.amount,
+            RollOp::Add => Some((label, distribution.min())),
+            RollOp::Sub => Some((label, -distribution.min())),
+            RollOp::Mul | RollOp::Div => None,
         }
     }
 
-    fn format(&self) -> String {
-        format!("{} {}", self.op.format(), self.amount)
+    fn apply(&self, to: f64, amount: f64) -> f64 {
+        match self.op {
+            RollOp::Add => to + amount,
+            RollOp::Sub => to - amount,
+            RollOp::Mul => to * amount,
+            RollOp::Div => to / amount,
+        }
+    }
+
+    /// Format this modifier, showing the values of any variables in place of
+    /// their names if resolved is set.
+    fn format_with(&self, resolved: bool) -> String {
+        // Operators are left associative, so an operand of the same
+        // precedence needs parentheses to preserve its grouping.
+        let operand = if self.operand.precedence() <= self.op.precedence() {
+            format!("({})", self.operand.format_with(resolved))
+        } else {
+            self.operand.format_with(resolved)
+        };
+        format!("{} {}", self.op.format(), operand)
     }
 }
 
+/// A group of dice of the same size rolled together, e.g. 4d6k3.
 #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
-pub struct Roll {
+struct Dice {
     quantity: u32,
     size: u32,
     suff: RollSuff,
-    mods: Vec<RollMod>,
+    reroll: Option<Reroll>,
+    explode: bool,
+
+    /// If set, this is a pool which counts successes instead of summing.
+    #[serde(default)]
+    count: Option<Count>,
 }
 
-impl Roll {
-    pub fn new(quantity: u32, size: u32) -> Self {
+impl Dice {
+    fn new(quantity: u32, size: u32) -> Self {
         Self {
             quantity,
             size,
             suff: RollSuff::None,
-            mods: Vec::new(),
+            reroll: None,
+            explode: false,
+            count: None,
         }
     }
 
-    pub fn parse(text: &str) -> Option<Self> {
-        parse_roll(text)
+    /// Amount a single result contributes to the total, either its value or
+    /// its number of successes in a pool.
+    fn face_value(&self, value: u32) -> i64 {
+        match self.count {
+            Some(count) => count.score(value),
+            None => value as i64,
+        }
     }
 
-    pub fn plus(mut self, amount: f64) -> Self {
-        self.mods.push(RollMod {
-            op: RollOp::Add,
-            amount,
-        });
-        self
+    /// Roll a single die, applying the reroll rule. Returns the final result
+    /// and any results which were rerolled.
+    fn roll_single(&self, rng: &DiceRng) -> (u32, Vec<u32>) {
+        let mut value = rng.roll(self.size);
+        let mut rerolled = Vec::new();
+        if let Some(reroll) = self.reroll {
+            while reroll.when.matches(value) && rerolled.len() < MAX_REPEATS {
+                rerolled.push(value);
+                value = rng.roll(self.size);
+                if reroll.once {
+                    break;
+                }
+            }
+        }
+        (value, rerolled)
     }
 
-    pub fn resolve(self) -> RollOutcome {
-        let mut results = Vec::new();
-        for _ in 0..self.quantity.max(1) {
-            results.push(rand::random_range(1..=self.size));
+    /// Roll one die of this group, rolling again each time it explodes.
+    fn roll_die(&self, rng: &DiceRng) -> DieResult {
+        let mut die = DieResult::default();
+        loop {
+            let (value, rerolled) = self.roll_single(rng);
+            die.rerolled.extend(rerolled);
+            die.rolls.push(value);
+            if !self.explode
+                || value != self.size
+                || die.rolls.len() > MAX_REPEATS
+            {
+                return die;
+            }
         }
+    }
 
-        let rolls_total = match self.suff {
-            RollSuff::None => results.iter().copied().sum(),
-            RollSuff::Advantage => results.iter().copied().max().unwrap_or(0),
-            RollSuff::Disadvantage => {
-                results.iter().copied().min().unwrap_or(0)
-            }
-            RollSuff::Keep(n) => {
-                let mut sorted = results.clone();
-                sorted.sort();
-                sorted.reverse();
-                let n = (n as usize).min(sorted.len());
-                (&sorted[0..n]).iter().copied().sum()
-            }
+    fn roll(&self, rng: &DiceRng) -> Vec<DieResult> {
+        let mut results: Vec<DieResult> = (0..self.quantity.max(1))
+            .map(|_| self.roll_die(rng))
+            .collect();
+        self.select(&mut results);
+        results
+    }
+
+    /// Mark the dice which don't count towards the total as dropped.
+    fn select(&self, results: &mut [DieResult]) {
+        let Some((highest, n)) = self.suff.keep_rule(results.len() as u32)
+        else {
+            return;
         };
 
-        let mut value = rolls_total as f64;
-        for modifier in &self.mods {
-            value = modifier.apply(value);
+        let mut order: Vec<usize> = (0..results.len()).collect();
+        order.sort_by_key(|&i| results[i].value());
+        if highest {
+            order.reverse();
+        }
+        for &i in order.iter().skip(n as usize) {
+            results[i].dropped = true;
         }
+    }
 
-        RollOutcome {
-            roll: self,
-            results,
-            value,
+    /// Total of the dice which weren't dropped. Each explosion of a die in a
+    /// pool counts as a separate die.
+    fn total(&self, results: &[DieResult]) -> i64 {
+        results
+            .iter()
+            .filter(|r| !r.dropped)
+            .flat_map(|r| &r.rolls)
+            .map(|&v| self.face_value(v))
+            .sum()
+    }
+
+    /// Probability of each face of a single die, after applying the reroll
+    /// rule. Index 0 holds the probability of rolling a 1.
+    fn face_probabilities(&self) -> Vec<f64> {
+        let size = self.size.max(1);
+        let fresh = 1.0 / size as f64;
+        let mut probs = vec![fresh; size as usize];
+        if let Some(reroll) = self.reroll {
+            let times = if reroll.once { 1 } else { MAX_REPEATS };
+            for _ in 0..times {
+                let rerolled: f64 = (1..=size)
+                    .filter(|&v| reroll.when.matches(v))
+                    .map(|v| probs[v as usize - 1])
+                    .sum();
+                if rerolled == 0.0 {
+                    break;
+                }
+                for v in 1..=size {
+                    let kept = if reroll.when.matches(v) {
+                        0.0
+                    } else {
+                        probs[v as usize - 1]
+                    };
+                    probs[v as usize - 1] = kept + rerolled * fresh;
+                }
+            }
         }
+        probs
     }
 
-    fn format(&self) -> String {
-        let mods = if self.mods.is_empty() {
-            String::new()
-        } else {
-            let mods = self
-                .mods
-                .iter()
-                .map(|m| m.format())
-                .collect::<Vec<String>>()
-                .join(" ");
-            format!(" {mods}")
-        };
+    /// Distribution of the result of a single die of this group, including
+    /// explosions. Chains of explosions are cut off once their probability
+    /// becomes negligible.
+    fn die_distribution(&self) -> Distribution {
+        let faces = self.face_probabilities();
+        let size = self.size.max(1);
+        let max_depth = if self.explode { MAX_REPEATS } else { 0 };
+        let mut pairs = Vec::new();
+        let mut chance = 1.0;
+        let mut offset = 0;
+        for depth in 0..=max_depth {
+            let last = if depth == max_depth { size } else { size - 1 };
+            for v in 1..=last {
+                pairs.push((
+                    (offset + self.face_value(v)) as f64,
+                    chance * faces[v as usize - 1],
+                ));
+            }
+            chance *= faces[size as usize - 1];
+            offset += self.face_value(size);
+            if chance < NEGLIGIBLE {
+                break;
+            }
+        }
+        Distribution::from_pairs(pairs)
+    }
+
+    /// Distribution of the total of this group of dice.
+    fn distribution(&self) -> Result<Distribution, String> {
+        let die = self.die_distribution();
+        let quantity = self.quantity.max(1);
+        match self.suff.keep_rule(quantity) {
+            None => {
+                // Adding each die pairs every total so far with every face,
+                // so the work grows with the square of the quantity.
+                let work = (die.0.len() * quantity as usize).saturating_pow(2);
+                if work / 2 > MAX_OUTCOMES {
+                    return Err(TOO_MANY_OUTCOMES.to_string());
+                }
+                (1..quantity).try_fold(die.clone(), |dist, _| {
+                    dist.combine(&die, |a, b| a + b)
+                })
+            }
+            Some((highest, keep)) => {
+                // Each value processed updates every count of remaining and
+                // kept dice, for each kept total.
+                let faces = die.0.len();
+                let work = faces
+                    .saturating_mul(quantity as usize)
+                    .saturating_mul(quantity as usize)
+                    .saturating_mul(faces.saturating_mul(keep as usize));
+                if work > MAX_OUTCOMES {
+                    return Err(TOO_MANY_OUTCOMES.to_string());
+                }
+                Ok(keep_distribution(&die, quantity, keep, highest))
+            }
+        }
+    }
 
+    fn format(&self) -> String {
         format!(
-            "{}d{}{}{}",
+            "{}d{}{}{}{}{}",
             if self.quantity != 1 {
                 self.quantity.to_string()
             } else {
                 String::new()
             },
             self.size,
+            self.reroll.map(|r| r.format()).unwrap_or_default(),
+            if self.explode { "!" } else { "" },
             self.suff.format(),
-            mods
+            self.count.map(|c| c.format()).unwrap_or_default(),
         )
     }
 }
 
-#[derive(Clone)]
-pub struct RollOutcome {
-    roll: Roll,
-    results: Vec<u32>,
-    value: f64,
+/// A named value from the character sheet referenced in a roll, e.g. @dex.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Var {
+    name: String,
+
+    /// Value looked up for this variable when the roll was last bound.
+    value: Option<i64>,
 }
 
-impl RollOutcome {
-    fn into_roll(self) -> Roll {
-        self.roll
+impl Var {
+    fn value(&self) -> f64 {
+        self.value.unwrap_or(0) as f64
     }
 
-    pub fn clone_roll(&self) -> Roll {
-        self.roll.clone()
+    fn format(&self, resolved: bool) -> String {
+        match self.value {
+            Some(value) if resolved => value.to_string(),
+            _ => format!("@{}", self.name),
+        }
     }
+}
 
-    pub fn format_roll(&self) -> String {
-        self.roll.format()
-    }
+/// Node in the syntax tree of a roll expression.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+enum Expr {
+    Dice(Dice),
+    Const(f64),
+    Var(Var),
+    Neg(Box<Expr>),
+    Mod(Box<Expr>, RollMod),
 
-    pub fn format_results(&self) -> String {
-        self.results
-            .iter()
-            .map(|v| v.to_string())
-            .collect::<Vec<String>>()
-            .join(", ")
-    }
+    /// A term annotated with the type of damage it deals, e.g. 2d6 slashing.
+    Typed(Box<Expr>, String),
+}
 
-    pub fn format_value(&self) -> String {
-        if self.value.fract() == 0.0 {
-            format!("{}", self.value)
-        } else {
-            format!("{:.2}", self.value)
+impl Expr {
+    /// Precedence of the outermost operation in this expression, used to
+    /// decide where parentheses are needed when formatting.
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::Mod(_, modifier) => modifier.op.precedence(),
+            Self::Dice(_)
+            | Self::Const(_)
+            | Self::Var(_)
+            | Self::Neg(_)
+            | Self::Typed(..) => u8::MAX,
         }
     }
-}
 
-fn take_leading_int(text: &[char]) -> Option<(&[char], u32)> {
-    let mut started = false;
-    let mut val = 0;
-    for (i, c) in text.iter().enumerate() {
-        match c {
-            _ if c.is_whitespace() && !started => (),
-            v if v.is_digit(10) => {
-                val *= 10;
-                val += v.to_digit(10).unwrap();
-                started = true;
+    /// Evaluate this expression, rolling any dice in it and recording the
+    /// results of each group of dice in order.
+    fn resolve(&self, rng: &DiceRng, results: &mut Vec<DiceOutcome>) -> f64 {
+        match self {
+            Self::Dice(dice) => {
+                let rolls = dice.roll(rng);
+                let total = dice.total(&rolls);
+                results.push(DiceOutcome {
+                    dice: dice.clone(),
+                    results: rolls,
+                });
+                total as f64
             }
-            _ if started => return Some((&text[i..], val)),
-            _ => return None,
+            Self::Const(value) => *value,
+            Self::Var(var) => var.value(),
+            Self::Neg(expr) => -expr.resolve(rng, results),
+            Self::Mod(expr, modifier) => {
+                let lhs = expr.resolve(rng, results);
+                let rhs = modifier.operand.resolve(rng, results);
+                modifier.apply(lhs, rhs)
+            }
+            Self::Typed(expr, _) => expr.resolve(rng, results),
         }
     }
 
-    if started { Some((&[], val)) } else { None }
-}
-
-fn next_char(text: &[char]) -> Option<(&[char], char)> {
-    let mut stripped = text;
-    while !stripped.is_empty() && stripped[0].is_whitespace() {
-        stripped = &stripped[1..];
+    /// Split the value of this expression into the amounts of each type of
+    /// damage, using the recorded results of each group of dice in the same
+    /// order as they were rolled. Terms without a type have type None.
+    fn damage<'a>(
+        &self,
+        results: &mut impl Iterator<Item = &'a DiceOutcome>,
+    ) -> Vec<(Option<String>, f64)> {
+        let sum = |parts: &[(Option<String>, f64)]| {
+            parts.iter().map(|(_, v)| v).sum::<f64>()
+        };
+        match self {
+            Self::Dice(dice) => {
+                let total = results
+                    .next()
+                    .map(|outcome| dice.total(&outcome.results))
+                    .unwrap_or_default();
+                vec![(None, total as f64)]
+            }
+            Self::Const(value) => vec![(None, *value)],
+            Self::Var(var) => vec![(None, var.value())],
+            Self::Neg(expr) => expr
+                .damage(results)
+                .into_iter()
+                .map(|(kind, v)| (kind, -v))
+                .collect(),
+            Self::Mod(expr, modifier) => {
+                let mut lhs = expr.damage(results);
+                let rhs = modifier.operand.damage(results);
+                match (modifier.op, rhs.as_slice(), lhs.as_slice()) {
+                    (RollOp::Add, _, _) => lhs.extend(rhs),
+                    (RollOp::Sub, _, _) => {
+                        lhs.extend(rhs.into_iter().map(|(k, v)| (k, -v)))
+                    }
+                    // Scaling by an untyped amount scales each type, e.g.
+                    // doubling on a critical hit.
+                    (_, [(None, amount)], _) => {
+                        for (_, v) in &mut lhs {
+                            *v = modifier.apply(*v, *amount);
+                        }
+                    }
+                    (RollOp::Mul, _, [(None, amount)]) => {
+                        let amount = *amount;
+                        lhs = rhs
+                            .into_iter()
+                            .map(|(k, v)| (k, amount * v))
+                            .collect();
+                    }
+                    _ => {
+                        lhs = vec![(None, modifier.apply(sum(&lhs), sum(&rhs)))]
+                    }
+                }
+                lhs
+            }
+            Self::Typed(expr, kind) => {
+                vec![(Some(kind.clone()), sum(&expr.damage(results)))]
+            }
+        }
     }
 
-    if !stripped.is_empty() {
-        Some((&stripped[1..], stripped[0]))
-    } else {
-        None
+    /// Exact distribution of the value of this expression, or an error if
+    /// it has too many outcomes to calculate.
+    fn distribution(&self) -> Result<Distribution, String> {
+        match self {
+            Self::Dice(dice) => dice.distribution(),
+            Self::Const(value) => Ok(Distribution::constant(*value)),
+            Self::Var(var) => Ok(Distribution::constant(var.value())),
+            Self::Neg(expr) => Ok(expr.distribution()?.map(|v| -v)),
+            Self::Mod(expr, modifier) => expr
+                .distribution()?
+                .combine(&modifier.operand.distribution()?, |a, b| {
+                    modifier.apply(a, b)
+                }),
+            Self::Typed(expr, _) => expr.distribution(),
+        }
     }
-}
 
-fn take_leading_number(text: &[char]) -> Option<(&[char], f64)> {
-    let mut num = String::new();
-    let mut text = text;
-    while let Some((rest, c)) = next_char(text) {
-        match c {
-            '.' => {
-                if !num.contains('.') {
-                    num.push('.');
+    /// Look up the value of each variable in this expression, returning the
+    /// name of the first which couldn't be found.
+    fn bind(
+        &mut self,
+        lookup: &impl Fn(&str) -> Option<i64>,
+    ) -> Result<(), String> {
+        match self {
+            Self::Dice(_) | Self::Const(_) => Ok(()),
+            Self::Var(var) => {
+                var.value = lookup(&var.name);
+                if var.value.is_some() {
+                    Ok(())
                 } else {
-                    return num.parse().ok().map(|v| (text, v));
+                    Err(var.name.clone())
                 }
             }
-            _ if c.is_digit(10) => num.push(c),
-            _ => return num.parse().ok().map(|v| (text, v)),
+            Self::Neg(expr) | Self::Typed(expr, _) => expr.bind(lookup),
+            Self::Mod(expr, modifier) => {
+                expr.bind(lookup)?;
+                modifier.operand.bind(lookup)
+            }
         }
-
-        text = rest;
-    }
-    num.parse().ok().map(|v| (text, v))
-}
-
-fn expect(expected: char, text: &[char]) -> Option<&[char]> {
-    let (rest, actual) = next_char(text)?;
-    if actual == expected { Some(rest) } else { None }
-}
-
-fn trim_whitespace(mut text: &[char]) -> &[char] {
-    while !text.is_empty() && text[0].is_whitespace() {
-        text = &text[1..];
     }
-    text
-}
 
-fn parse_roll_suff_mods(
-    text: &[char],
-) -> Option<(&[char], RollSuff, Vec<RollMod>)> {
-    let mut suff = RollSuff::None;
-    let mut mods = Vec::new();
-
-    let (mut text, c) = next_char(text)?;
-    match c {
-        'a' => suff = RollSuff::Advantage,
-        'd' => suff = RollSuff::Disadvantage,
-        'k' => match take_leading_int(text) {
-            Some((rest, num)) => {
-                text = rest;
-                suff = RollSuff::Keep(num);
-            }
-            None => suff = RollSuff::Keep(1),
-        },
-        c if RollOp::from(c).is_some() => {
-            let op = RollOp::from(c).unwrap();
-            let val;
-            (text, val) = take_leading_number(text)?;
-            mods.push(RollMod { op, amount: val });
+    /// Collect the labelled modifiers in this expression in order.
+    fn breakdown(&self, parts: &mut Vec<(String, f64)>) {
+        match self {
+            Self::Dice(_) | Self::Const(_) | Self::Var(_) => {}
+            Self::Neg(expr) | Self::Typed(expr, _) => expr.breakdown(parts),
+            Self::Mod(expr, modifier) => {
+                expr.breakdown(parts);
+                parts.extend(modifier.contribution());
+                modifier.operand.breakdown(parts);
+            }
         }
-        _ => return None,
     }
 
-    match parse_roll_suff_mods(text) {
-        Some((text, o_suff, more_mods)) => {
-            mods.extend(more_mods);
-            let suff = if o_suff == RollSuff::None {
-                suff
-            } else {
-                o_suff
-            };
-            Some((text, suff, mods))
+    /// Copy of this expression with twice as many of each kind of dice
+    /// which are summed. Dice which keep some results or count successes,
+    /// e.g. 2d20kh1, are left unchanged.
+    fn double_dice(&self) -> Self {
+        match self {
+            Self::Dice(dice)
+                if matches!(dice.suff, RollSuff::None)
+                    && dice.count.is_none() =>
+            {
+                Self::Dice(Dice {
+                    quantity: dice.quantity.max(1) * 2,
+                    ..dice.clone()
+                })
+            }
+            Self::Dice(_) => self.clone(),
+            Self::Const(_) | Self::Var(_) => self.clone(),
+            Self::Neg(expr) => Self::Neg(Box::new(expr.double_dice())),
+            Self::Typed(expr, kind) => {
+                Self::Typed(Box::new(expr.double_dice()), kind.clone())
+            }
+            Self::Mod(expr, modifier) => Self::Mod(
+                Box::new(expr.double_dice()),
+                RollMod {
+                    operand: Box::new(modifier.operand.double_dice()),
+                    ..modifier.clone()
+                },
+            ),
         }
-        None => Some((text, suff, mods)),
     }
-}
-
-fn parse_one_roll(text: &[char]) -> Option<(Roll, &[char])> {
-    let mut roll = Roll {
-        quantity: 0,
-        size: 0,
-        suff: RollSuff::None,
-        mods: Vec::new(),
-    };
 
-    let (text, quantity) =
-        if let Some((text, quantity)) = take_leading_int(text) {
-            (expect('d', text)?, quantity)
-        } else {
-            (expect('d', trim_whitespace(text))?, 1)
-        };
-    roll.quantity = quantity;
-    let (mut text, size) = take_leading_int(text)?;
-    roll.size = size;
-    if let Some((rest, suff, mods)) = parse_roll_suff_mods(text) {
-        text = rest;
-        roll.suff = suff;
-        roll.mods = mods;
+    /// Check whether any node in this expression matches the predicate.
+    fn contains(&self, predicate: &impl Fn(&Expr) -> bool) -> bool {
+        predicate(self)
+            || match self {
+                Self::Dice(_) | Self::Const(_) | Self::Var(_) => false,
+                Self::Neg(expr) | Self::Typed(expr, _) => {
+                    expr.contains(predicate)
+                }
+                Self::Mod(expr, modifier) => {
+                    expr.contains(predicate)
+                        || modifier.operand.contains(predicate)
+                }
+            }
     }
 
-    Some((roll, text))
-}
-
-fn parse_roll(text: &str) -> Option<Roll> {
-    let roll =
-        parse_one_roll(text.chars().collect::<Vec<char>>().as_slice())?.0;
-    Some(roll)
-}
+    fn has_variables(&self) -> bool {
+        self.contains(&|expr| matches!(expr, Self::Var(_)))
+    }
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    fn has_damage_types(&self) -> bool {
+        self.contains(&|expr| matches!(expr, Self::Typed(..)))
+    }
 
-    #[test]
-    fn test_format_add_mod() {
-        let modifier = RollMod {
-            op: RollOp::Add,
-            amount: 3.0,
-        };
-        assert_eq!(modifier.format(), "+ 3");
+    /// Whether this expression includes a pool which counts successes.
+    fn counts_successes(&self) -> bool {
+        self.contains(
+            &|expr| matches!(expr, Self::Dice(dice) if dice.count.is_some()),
+        )
     }
 
-    #[test]
-    fn test_format_sub_mod() {
-        let modifier = RollMod {
-            op: RollOp::Sub,
-            amount: 3.2,
-        };
-        assert_eq!(modifier.format(), "- 3.2");
+    fn format(&self) -> String {
+        self.format_with(false)
     }
 
-    #[test]
+    /// Format this expression, showing the values of any variables in place
+    /// of their names if resolved is set.
+    fn format_with(&self, resolved: bool) -> String {
+        match self {
+            Self::Dice(dice) => dice.format(),
+            Self::Const(value) => value.to_string(),
+            Self::Var(var) => var.format(resolved),
+            Self::Neg(expr) => {
+                if expr.precedence() == u8::MAX {
+                    format!("-{}", expr.format_with(resolved))
+                } else {
+                    format!("-({})", expr.format_with(resolved))
+                }
+            }
+            Self::Typed(expr, kind) => {
+                if expr.precedence() == u8::MAX {
+                    format!("{} {kind}", expr.format_with(resolved))
+                } else {
+                    format!("({}) {kind}", expr.format_with(resolved))
+                }
+            }
+            Self::Mod(expr, modifier) => {
+                let expr_text = expr.format_with(resolved);
+                let modifier_text = modifier.format_with(resolved);
+                if expr.precedence() < modifier.op.precedence() {
+                    format!("({expr_text}) {modifier_text}")
+                } else {
+                    format!("{expr_text} {modifier_text}")
+                }
+            }
+        }
+    }
+}
+
+/// Rule for increasing damage on a critical hit.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum CritRule {
+    /// Double the total of the roll, per the core rules.
+    #[default]
+    DoubleTotal,
+
+    /// Roll twice as many dice, leaving fixed modifiers as they are.
+    DoubleDice,
+}
+
+impl CritRule {
+    pub fn toggle(self) -> Self {
+        match self {
+            Self::DoubleTotal => Self::DoubleDice,
+            Self::DoubleDice => Self::DoubleTotal,
+        }
+    }
+
+    pub fn format(&self) -> &'static str {
+        match self {
+            Self::DoubleTotal => "double total",
+            Self::DoubleDice => "double dice",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Roll {
+    expr: Expr,
+
+    /// Extra dice added only on a critical hit, e.g. from the deadly trait.
+    #[serde(default)]
+    crit: Option<Expr>,
+
+    /// Difficulty class this roll is a check against, if any.
+    dc: Option<i64>,
+
+    /// What this roll is for, e.g. the skill or macro rolled.
+    #[serde(default)]
+    label: Option<String>,
+
+    /// Modifiers which didn't apply to this roll because a larger modifier
+    /// of the same type did.
+    #[serde(default)]
+    suppressed: Vec<String>,
+}
+
+impl Roll {
+    pub fn new(quantity: u32, size: u32) -> Self {
+        Self {
+            expr: Expr::Dice(Dice::new(quantity, size)),
+            crit: None,
+            dc: None,
+            label: None,
+            suppressed: Vec::new(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        parse_roll(text)
+    }
+
+    pub fn plus(self, amount: f64) -> Self {
+        let (op, amount) = if amount < 0.0 {
+            (RollOp::Sub, -amount)
+        } else {
+            (RollOp::Add, amount)
+        };
+        Self {
+            expr: Expr::Mod(
+                Box::new(self.expr),
+                RollMod::new(op, Expr::Const(amount)),
+            ),
+            ..self
+        }
+    }
+
+    /// Add a fixed amount to this roll, labelled with its source so that it
+    /// is itemised in the breakdown of the roll.
+    pub fn plus_labelled(self, amount: f64, label: impl ToString) -> Self {
+        let mut roll = self.plus(amount);
+        if let Expr::Mod(_, modifier) = &mut roll.expr {
+            modifier.label = Some(label.to_string());
+        }
+        roll
+    }
+
+    /// Convert this into the roll made on a critical hit, increasing it per
+    /// the rule and adding any extra critical dice.
+    pub fn critical(self, rule: CritRule) -> Self {
+        let expr = match rule {
+            CritRule::DoubleTotal => Expr::Mod(
+                Box::new(self.expr),
+                RollMod::new(RollOp::Mul, Expr::Const(2.0)),
+            ),
+            CritRule::DoubleDice => self.expr.double_dice(),
+        };
+        let expr = match self.crit {
+            Some(extra) => {
+                Expr::Mod(Box::new(expr), RollMod::new(RollOp::Add, extra))
+            }
+            None => expr,
+        };
+        Self {
+            expr,
+            crit: None,
+            ..self
+        }
+    }
+
+    /// Record what this roll is for, e.g. the skill or macro rolled.
+    pub fn labelled(self, label: impl ToString) -> Self {
+        Self {
+            label: Some(label.to_string()),
+            ..self
+        }
+    }
+
+    /// Copy of this roll which isn't a check against a DC.
+    pub fn without_dc(self) -> Self {
+        Self { dc: None, ..self }
+    }
+
+    /// Record the modifiers which were suppressed when making this roll.
+    pub fn suppressing(self, suppressed: Vec<String>) -> Self {
+        Self { suppressed, ..self }
+    }
+
+    /// Make this roll a check against the provided DC.
+    pub fn against(self, dc: i64) -> Self {
+        Self {
+            dc: Some(dc),
+            ..self
+        }
+    }
+
+    pub fn resolve(self, rng: &DiceRng) -> RollOutcome {
+        let seed = rng.seed();
+        let position = rng.position();
+        let mut results = Vec::new();
+        let value = self.expr.resolve(rng, &mut results);
+        let degree = self
+            .dc
+            .map(|dc| Degree::of(value, natural_d20(&results), dc));
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|t| t.as_secs())
+            .unwrap_or_default();
+        RollOutcome {
+            roll: self,
+            results,
+            value,
+            degree,
+            seed,
+            position,
+            timestamp,
+            defenses: None,
+        }
+    }
+
+    /// Look up the values of the sheet variables referenced in this roll,
+    /// e.g. @dex. Returns the name of the first unknown variable on failure.
+    pub fn bind(
+        mut self,
+        lookup: impl Fn(&str) -> Option<i64>,
+    ) -> Result<Self, String> {
+        self.expr.bind(&lookup)?;
+        if let Some(crit) = &mut self.crit {
+            crit.bind(&lookup)?;
+        }
+        Ok(self)
+    }
+
+    /// DC this roll is a check against, if any.
+    pub fn dc(&self) -> Option<i64> {
+        self.dc
+    }
+
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    /// Calculate the probability of each possible total of this roll.
+    pub fn distribution(&self) -> Result<Distribution, String> {
+        self.expr.distribution()
+    }
+
+    pub fn format(&self) -> String {
+        format!("{}{}", self.expr.format(), self.format_suffix())
+    }
+
+    /// Format the parts of this roll which follow its expression, i.e. any
+    /// critical dice and the DC.
+    fn format_suffix(&self) -> String {
+        let mut suffix = String::new();
+        if let Some(crit) = &self.crit {
+            suffix.push_str(&format!(" crit {}", crit.format()));
+        }
+        if let Some(dc) = self.dc {
+            suffix.push_str(&format!(" vs DC {dc}"));
+        }
+        suffix
+    }
+
+    /// Format the labelled modifiers of this roll, e.g. DEX +3, Trained +5.
+    pub fn format_breakdown(&self) -> String {
+        let mut parts = Vec::new();
+        self.expr.breakdown(&mut parts);
+        parts
+            .iter()
+            .map(|(label, value)| format!("{label} {value:+}"))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// Format the modifiers suppressed when making this roll, if any.
+    pub fn format_suppressed(&self) -> String {
+        if self.suppressed.is_empty() {
+            String::new()
+        } else {
+            format!("Suppressed: {}", self.suppressed.join(", "))
+        }
+    }
+
+    /// Format this roll, followed by the values substituted for its
+    /// variables if it has any, e.g. d20 + @dex = d20 + 3.
+    pub fn format_resolved(&self) -> String {
+        if !self.expr.has_variables() {
+            return self.format();
+        }
+
+        format!(
+            "{} = {}{}",
+            self.expr.format(),
+            self.expr.format_with(true),
+            self.format_suffix()
+        )
+    }
+}
+
+/// Format a number, rounding any fraction.
+pub(crate) fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{value}")
+    } else {
+        format!("{value:.2}")
+    }
+}
+
+/// Combine amounts of damage by type, preserving the order in which types
+/// first appear. Untyped amounts are given the type of the next typed amount,
+/// or the last if there are none after them. If nothing is typed, the result
+/// is empty.
+fn damage_by_type(parts: Vec<(Option<String>, f64)>) -> Vec<(String, f64)> {
+    let mut totals: Vec<(String, f64)> = Vec::new();
+    let mut untyped = 0.0;
+    for (kind, value) in parts {
+        let Some(kind) = kind else {
+            untyped += value;
+            continue;
+        };
+        let value = value + std::mem::take(&mut untyped);
+        match totals.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, total)) => *total += value,
+            None => totals.push((kind, value)),
+        }
+    }
+    if let Some((_, total)) = totals.last_mut() {
+        *total += untyped;
+    }
+    totals
+}
+
+/// Immunities, resistances and weaknesses of a target to types of damage.
+/// The type all matches every type of damage.
+#[derive(
+    Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct Defenses {
+    immunities: Vec<String>,
+    resistances: Vec<(String, i64)>,
+    weaknesses: Vec<(String, i64)>,
+}
+
+impl Defenses {
+    /// Parse a list of defenses separated by commas, e.g.
+    /// immune poison, resist fire 5, weak cold 3.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut defenses = Self::default();
+        for entry in text.split(',').filter(|e| !e.trim().is_empty()) {
+            let words: Vec<String> =
+                entry.split_whitespace().map(str::to_lowercase).collect();
+            let invalid = || {
+                format!(
+                    "Expected immune TYPE, resist TYPE N or weak TYPE N, \
+                    found '{}'.",
+                    entry.trim()
+                )
+            };
+            match words.as_slice() {
+                [kind, name @ ..]
+                    if !name.is_empty()
+                        && matches!(kind.as_str(), "immune" | "immunity") =>
+                {
+                    defenses.immunities.push(name.join(" "));
+                }
+                [kind, name @ .., amount] if !name.is_empty() => {
+                    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+                    let entry = (name.join(" "), amount);
+                    match kind.as_str() {
+                        "resist" | "resistance" => {
+                            defenses.resistances.push(entry)
+                        }
+                        "weak" | "weakness" => defenses.weaknesses.push(entry),
+                        _ => return Err(invalid()),
+                    }
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(defenses)
+    }
+
+    /// Highest amount in the list which applies to the type of damage.
+    fn highest(amounts: &[(String, i64)], kind: &str) -> Option<i64> {
+        amounts
+            .iter()
+            .filter(|(k, _)| k == kind || k == "all")
+            .map(|&(_, amount)| amount)
+            .max()
+    }
+
+    /// Adjust an amount of damage of some type. Immunity is applied first,
+    /// then weakness, then resistance.
+    fn apply(&self, kind: &str, amount: f64) -> f64 {
+        if self.immunities.iter().any(|k| k == kind || k == "all") {
+            return 0.0;
+        }
+
+        let mut amount = amount.max(0.0);
+        if amount > 0.0
+            && let Some(weakness) = Self::highest(&self.weaknesses, kind)
+        {
+            amount += weakness as f64;
+        }
+        if let Some(resistance) = Self::highest(&self.resistances, kind) {
+            amount = (amount - resistance as f64).max(0.0);
+        }
+        amount
+    }
+
+    pub fn format(&self) -> String {
+        let immunities = self.immunities.iter().map(|k| format!("immune {k}"));
+        let resistances = self
+            .resistances
+            .iter()
+            .map(|(k, n)| format!("resist {k} {n}"));
+        let weaknesses =
+            self.weaknesses.iter().map(|(k, n)| format!("weak {k} {n}"));
+        immunities
+            .chain(resistances)
+            .chain(weaknesses)
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+/// A named roll expression saved on the sheet, e.g. sneak attack damage.
+#[derive(
+    Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct RollMacro {
+    pub name: String,
+    pub expression: String,
+}
+
+/// Probabilities below this are treated as impossible when calculating
+/// distributions, to keep unbounded rolls like exploding dice finite.
+const NEGLIGIBLE: f64 = 1e-12;
+
+/// Most pairs of outcomes considered when calculating a distribution, to
+/// keep the calculation quick enough to run while drawing.
+const MAX_OUTCOMES: usize = 1_000_000;
+
+const TOO_MANY_OUTCOMES: &str = "Too many outcomes to calculate odds.";
+
+/// Probability distribution of the outcome of a roll, as pairs of value and
+/// probability sorted by value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Distribution(Vec<(f64, f64)>);
+
+impl Distribution {
+    fn constant(value: f64) -> Self {
+        Self(vec![(value, 1.0)])
+    }
+
+    /// Create a distribution from pairs of value and probability, merging
+    /// duplicate values.
+    fn from_pairs(mut pairs: Vec<(f64, f64)>) -> Self {
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut merged: Vec<(f64, f64)> = Vec::with_capacity(pairs.len());
+        for (value, p) in pairs {
+            if p == 0.0 {
+                continue;
+            }
+            match merged.last_mut() {
+                Some(last) if last.0 == value => last.1 += p,
+                _ => merged.push((value, p)),
+            }
+        }
+        Self(merged)
+    }
+
+    fn map(&self, f: impl Fn(f64) -> f64) -> Self {
+        Self::from_pairs(self.0.iter().map(|&(v, p)| (f(v), p)).collect())
+    }
+
+    /// Distribution of the result of applying f to independent outcomes of
+    /// this and another distribution, or an error if there are too many
+    /// pairs of outcomes.
+    fn combine(
+        &self,
+        other: &Self,
+        f: impl Fn(f64, f64) -> f64,
+    ) -> Result<Self, String> {
+        let size = self.0.len().saturating_mul(other.0.len());
+        if size > MAX_OUTCOMES {
+            return Err(TOO_MANY_OUTCOMES.to_string());
+        }
+        let mut pairs = Vec::with_capacity(size);
+        for &(a, pa) in &self.0 {
+            for &(b, pb) in &other.0 {
+                pairs.push((f(a, b), pa * pb));
+            }
+        }
+        Ok(Self::from_pairs(pairs))
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.0.iter().map(|(v, p)| v * p).sum()
+    }
+
+    pub fn min(&self) -> f64 {
+        self.0.first().map(|(v, _)| *v).unwrap_or(0.0)
+    }
+
+    pub fn max(&self) -> f64 {
+        self.0.last().map(|(v, _)| *v).unwrap_or(0.0)
+    }
+
+    /// Smallest value which at least the provided fraction of outcomes fall
+    /// at or below.
+    pub fn percentile(&self, fraction: f64) -> f64 {
+        let mut cumulative = 0.0;
+        for &(value, p) in &self.0 {
+            cumulative += p;
+            if cumulative >= fraction - NEGLIGIBLE {
+                return value;
+            }
+        }
+        self.max()
+    }
+
+    /// Probability of an outcome of at least the provided value, e.g. the
+    /// chance to meet a DC.
+    pub fn chance_at_least(&self, value: f64) -> f64 {
+        self.0
+            .iter()
+            .filter(|(v, _)| *v >= value)
+            .map(|(_, p)| p)
+            .sum()
+    }
+
+    /// Group the distribution into at most the provided number of buckets of
+    /// equal width for display as a histogram. Returns the lowest and highest
+    /// value in each bucket with the probability of the bucket.
+    pub fn buckets(&self, max_buckets: usize) -> Vec<(f64, f64, f64)> {
+        let max_buckets = max_buckets.max(1);
+        if self.0.len() <= max_buckets {
+            return self.0.iter().map(|&(v, p)| (v, v, p)).collect();
+        }
+
+        let min = self.min();
+        let width = (self.max() - min) / max_buckets as f64;
+        let mut buckets = vec![None; max_buckets];
+        for &(value, p) in &self.0 {
+            let index = (((value - min) / width) as usize).min(max_buckets - 1);
+            let bucket = buckets[index].get_or_insert((value, value, 0.0));
+            bucket.1 = value;
+            bucket.2 += p;
+        }
+        buckets.into_iter().flatten().collect()
+    }
+}
+
+/// Number of ways to choose k items from n.
+fn choose(n: u32, k: u32) -> f64 {
+    (0..k).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+/// Calculate the distribution of the sum of the highest (or lowest) keep dice
+/// out of quantity dice which each follow the provided distribution.
+///
+/// Works through die results from most to least preferred, tracking how
+/// many dice are still unassigned and how many have been kept so far. At
+/// each value, the number of the remaining dice showing that value is
+/// binomially distributed.
+fn keep_distribution(
+    die: &Distribution,
+    quantity: u32,
+    keep: u32,
+    highest: bool,
+) -> Distribution {
+    let mut values = die.0.clone();
+    if highest {
+        values.reverse();
+    }
+
+    // Map from (remaining dice, kept dice) to distribution of the kept total.
+    let mut states: std::collections::HashMap<(u32, u32), Distribution> =
+        std::collections::HashMap::from([(
+            (quantity, 0),
+            Distribution::constant(0.0),
+        )]);
+    let mut mass_remaining = 1.0;
+    for (i, &(value, p)) in values.iter().enumerate() {
+        // Probability that a remaining die shows this value, given that it
+        // shows one of the values not yet processed.
+        let q = if i == values.len() - 1 {
+            1.0
+        } else {
+            (p / mass_remaining).min(1.0)
+        };
+        mass_remaining -= p;
+
+        let mut next: std::collections::HashMap<(u32, u32), Vec<(f64, f64)>> =
+            std::collections::HashMap::new();
+        for ((remaining, kept), dist) in states {
+            for count in 0..=remaining {
+                let chance = choose(remaining, count)
+                    * q.powi(count as i32)
+                    * (1.0 - q).powi((remaining - count) as i32);
+                if chance == 0.0 {
+                    continue;
+                }
+
+                let taken = count.min(keep - kept);
+                let added = value * taken as f64;
+                let kept = kept + taken;
+
+                // Once enough dice are kept, the rest don't matter.
+                let remaining =
+                    if kept == keep { 0 } else { remaining - count };
+                next.entry((remaining, kept)).or_default().extend(
+                    dist.0.iter().map(|&(v, p)| (v + added, p * chance)),
+                );
+            }
+        }
+        states = next
+            .into_iter()
+            .map(|(k, pairs)| (k, Distribution::from_pairs(pairs)))
+            .collect();
+    }
+
+    Distribution::from_pairs(
+        states.into_values().flat_map(|d| d.0.into_iter()).collect(),
+    )
+}
+
+/// Degree of success of a check against a DC.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum Degree {
+    CriticalFailure,
+    Failure,
+    Success,
+    CriticalSuccess,
+}
+
+impl Degree {
+    /// Determine the degree of success of a check with the provided total
+    /// against a DC. Beating the DC by 10 or more is a critical success and
+    /// failing by 10 or more a critical failure. A natural 20 or natural 1
+    /// then improves or worsens the result by one step.
+    pub fn of(total: f64, natural: Option<u32>, dc: i64) -> Self {
+        let dc = dc as f64;
+        let degree = if total >= dc + 10.0 {
+            Self::CriticalSuccess
+        } else if total >= dc {
+            Self::Success
+        } else if total <= dc - 10.0 {
+            Self::CriticalFailure
+        } else {
+            Self::Failure
+        };
+
+        match natural {
+            Some(20) => degree.step_up(),
+            Some(1) => degree.step_down(),
+            _ => degree,
+        }
+    }
+
+    fn step_up(self) -> Self {
+        match self {
+            Self::CriticalFailure => Self::Failure,
+            Self::Failure => Self::Success,
+            Self::Success | Self::CriticalSuccess => Self::CriticalSuccess,
+        }
+    }
+
+    fn step_down(self) -> Self {
+        match self {
+            Self::CriticalFailure | Self::Failure => Self::CriticalFailure,
+            Self::Success => Self::Failure,
+            Self::CriticalSuccess => Self::Success,
+        }
+    }
+
+    pub fn format(&self) -> &'static str {
+        match self {
+            Self::CriticalFailure => "Critical Failure",
+            Self::Failure => "Failure",
+            Self::Success => "Success",
+            Self::CriticalSuccess => "Critical Success",
+        }
+    }
+}
+
+/// Return the natural result of the first d20 in the provided results, if it
+/// contributes a single unexploded die to the total.
+fn natural_d20(results: &[DiceOutcome]) -> Option<u32> {
+    let outcome = results
+        .iter()
+        .find(|r| r.dice.size == 20 && r.dice.count.is_none())?;
+    let mut kept = outcome.results.iter().filter(|r| !r.dropped);
+    match (kept.next(), kept.next()) {
+        (Some(die), None) if die.rolls.len() == 1 => Some(die.value()),
+        _ => None,
+    }
+}
+
+/// The result of rolling a single die in a group.
+#[derive(
+    Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+struct DieResult {
+    /// Results rolled for this die. Each result after the first was rolled
+    /// because the previous one exploded.
+    rolls: Vec<u32>,
+
+    /// Results which were discarded and rerolled.
+    rerolled: Vec<u32>,
+
+    /// Whether this die was dropped and doesn't count towards the total.
+    dropped: bool,
+}
+
+impl DieResult {
+    fn value(&self) -> u32 {
+        self.rolls.iter().sum()
+    }
+
+    /// Format this die's result, e.g. 1->4 for a 1 rerolled into a 4, 6!+2
+    /// for a 6 that exploded into a 2 and [3] for a dropped 3.
+    fn format(&self) -> String {
+        let mut text = String::new();
+        for value in &self.rerolled {
+            text.push_str(&format!("{value}->"));
+        }
+        let rolls = self
+            .rolls
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<String>>()
+            .join("!+");
+        text.push_str(&rolls);
+        if self.dropped {
+            format!("[{text}]")
+        } else {
+            text
+        }
+    }
+}
+
+/// The individual results of rolling one group of dice in a roll.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct DiceOutcome {
+    dice: Dice,
+    results: Vec<DieResult>,
+}
+
+impl DiceOutcome {
+    fn format(&self) -> String {
+        self.results
+            .iter()
+            .map(|r| r.format())
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct RollOutcome {
+    roll: Roll,
+    results: Vec<DiceOutcome>,
+    value: f64,
+    degree: Option<Degree>,
+
+    /// Seed of the random stream this roll was drawn from.
+    seed: u64,
+
+    /// Position in the random stream at which this roll started.
+    position: u128,
+
+    /// Time this roll was made, in seconds since the Unix epoch.
+    #[serde(default)]
+    timestamp: u64,
+
+    /// Defenses of the target applied to the damage of this roll, if any.
+    #[serde(default)]
+    defenses: Option<Defenses>,
+}
+
+impl RollOutcome {
+    fn into_roll(self) -> Roll {
+        self.roll
+    }
+
+    pub fn clone_roll(&self) -> Roll {
+        self.roll.clone()
+    }
+
+    pub fn format_roll(&self) -> String {
+        self.roll.format_resolved()
+    }
+
+    pub fn format_breakdown(&self) -> String {
+        self.roll.format_breakdown()
+    }
+
+    pub fn format_suppressed(&self) -> String {
+        self.roll.format_suppressed()
+    }
+
+    /// Format the individual dice results. If the roll contains multiple
+    /// groups of dice, the results of each are labelled with the dice rolled.
+    pub fn format_results(&self) -> String {
+        if self.results.len() == 1 {
+            self.results[0].format()
+        } else {
+            self.results
+                .iter()
+                .map(|r| format!("{}: {}", r.dice.format(), r.format()))
+                .collect::<Vec<String>>()
+                .join("; ")
+        }
+    }
+
+    /// Roll again using the same random numbers as this outcome, reproducing
+    /// it exactly.
+    fn replay(&self) -> RollOutcome {
+        self.clone_roll()
+            .resolve(&DiceRng::at(self.seed, self.position))
+    }
+
+    /// Check that replaying this roll from its seed and position in the
+    /// random stream reproduces the recorded results.
+    pub fn verify(&self) -> bool {
+        let replay = self.replay();
+        replay.value == self.value
+            && replay.results.len() == self.results.len()
+            && replay
+                .results
+                .iter()
+                .zip(&self.results)
+                .all(|(a, b)| a.results == b.results)
+    }
+
+    /// Describe where in the random stream this roll was drawn from.
+    pub fn format_source(&self) -> String {
+        format!("seed {} position {}", self.seed, self.position)
+    }
+
+    /// Time at which this roll was made.
+    pub fn timestamp(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.timestamp)
+    }
+
+    /// Degree of success, if this roll was a check against a DC.
+    pub fn degree(&self) -> Option<Degree> {
+        self.degree
+    }
+
+    /// Whether this roll has damage split by type.
+    pub fn has_damage_types(&self) -> bool {
+        self.roll.expr.has_damage_types()
+    }
+
+    /// Amount of each type of damage dealt by this roll, before defenses.
+    fn damage(&self) -> Vec<(String, f64)> {
+        damage_by_type(self.roll.expr.damage(&mut self.results.iter()))
+    }
+
+    /// Apply the defenses of a target to the damage of this roll.
+    pub fn with_defenses(self, defenses: Defenses) -> Self {
+        Self {
+            defenses: Some(defenses),
+            ..self
+        }
+    }
+
+    /// Total of this roll, after applying any defenses to its damage.
+    pub fn total(&self) -> f64 {
+        match &self.defenses {
+            Some(defenses) if self.has_damage_types() => self
+                .damage()
+                .iter()
+                .map(|(kind, amount)| defenses.apply(kind, *amount))
+                .sum(),
+            _ => self.value,
+        }
+    }
+
+    /// Format the amount of each type of damage, e.g. 9 slashing, 4 fire,
+    /// showing adjusted amounts if defenses were applied, e.g. 4->0 fire.
+    pub fn format_damage(&self) -> String {
+        self.damage()
+            .iter()
+            .map(|(kind, amount)| {
+                let adjusted = self
+                    .defenses
+                    .as_ref()
+                    .map(|d| d.apply(kind, *amount))
+                    .unwrap_or(*amount);
+                if adjusted != *amount {
+                    format!(
+                        "{}->{} {kind}",
+                        format_number(*amount),
+                        format_number(adjusted)
+                    )
+                } else {
+                    format!("{} {kind}", format_number(*amount))
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// Label of the roll, if it was made for something in particular.
+    pub fn label(&self) -> Option<&str> {
+        self.roll.label()
+    }
+
+    /// Summarise this roll as a record for the exported roll log.
+    fn record(&self) -> RollRecord {
+        RollRecord {
+            timestamp: self.timestamp,
+            label: self.label().unwrap_or_default().to_string(),
+            expression: self.format_roll(),
+            dice: self.format_results(),
+            total: self.total(),
+        }
+    }
+
+    /// Format the total of this roll, or the number of successes if it
+    /// counts successes in a pool.
+    pub fn format_value(&self) -> String {
+        let total = self.total();
+        let value = format_number(total);
+        if !self.roll.expr.counts_successes() {
+            value
+        } else if total == 1.0 {
+            format!("{value} success")
+        } else {
+            format!("{value} successes")
+        }
+    }
+}
+
+/// Significance level below which the results of a die are reported as
+/// unlikely to come from a fair die.
+const FAIRNESS_THRESHOLD: f64 = 0.05;
+
+/// Number of times each face was rolled across a set of rolls, for dice of a
+/// single size.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaceCounts {
+    size: u32,
+    counts: Vec<u64>,
+}
+
+impl FaceCounts {
+    fn new(size: u32) -> Self {
+        Self {
+            size,
+            counts: vec![0; size as usize],
+        }
+    }
+
+    fn add(&mut self, value: u32) {
+        if let Some(count) = self.counts.get_mut(value as usize - 1) {
+            *count += 1;
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Number of times each face was rolled, starting from 1.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Number of times a die of this size was rolled.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Average result rolled.
+    pub fn average(&self) -> f64 {
+        let sum: u64 = (1..).zip(&self.counts).map(|(face, n)| face * n).sum();
+        sum as f64 / self.total().max(1) as f64
+    }
+
+    /// Average result of a fair die of this size.
+    pub fn expected(&self) -> f64 {
+        (self.size as f64 + 1.0) / 2.0
+    }
+
+    /// Whether each face is expected often enough for the chi-squared test to
+    /// be meaningful, which requires an expected count of at least 5.
+    pub fn enough_rolls(&self) -> bool {
+        self.size > 1 && self.total() >= 5 * self.size as u64
+    }
+
+    /// Pearson's chi-squared statistic for these counts against a fair die.
+    pub fn chi_squared(&self) -> f64 {
+        let expected = self.total() as f64 / self.size as f64;
+        if expected == 0.0 {
+            return 0.0;
+        }
+        self.counts
+            .iter()
+            .map(|&n| (n as f64 - expected).powi(2) / expected)
+            .sum()
+    }
+
+    /// Chance of a fair die producing counts at least this uneven.
+    pub fn p_value(&self) -> f64 {
+        let freedom = (self.size.max(2) - 1) as f64;
+        1.0 - lower_gamma_ratio(freedom / 2.0, self.chi_squared() / 2.0)
+    }
+
+    /// Describe whether these results look like those of a fair die.
+    pub fn verdict(&self) -> &'static str {
+        if !self.enough_rolls() {
+            "not enough rolls"
+        } else if self.p_value() < FAIRNESS_THRESHOLD {
+            "suspicious"
+        } else {
+            "looks fair"
+        }
+    }
+}
+
+/// Natural logarithm of the gamma function at x, which must be a positive
+/// multiple of 0.5.
+fn ln_gamma_half(x: f64) -> f64 {
+    let mut x = x;
+    let mut ln = 0.0;
+    while x > 1.0 {
+        x -= 1.0;
+        ln += x.ln();
+    }
+    if x == 0.5 {
+        ln + std::f64::consts::PI.sqrt().ln()
+    } else {
+        ln
+    }
+}
+
+/// Regularised lower incomplete gamma function P(a, x), for a a positive
+/// multiple of 0.5, calculated by its power series.
+fn lower_gamma_ratio(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+
+    // Terms are x^(a + n) e^-x / gamma(a + n + 1), summed in log space to
+    // avoid overflow for large x.
+    let mut ln_term = a * x.ln() - x - ln_gamma_half(a + 1.0);
+    let mut sum = 0.0;
+    for n in 1..100_000 {
+        let term = ln_term.exp();
+        sum += term;
+        if n as f64 > x && term < sum * 1e-12 {
+            break;
+        }
+        ln_term += x.ln() - (a + n as f64).ln();
+    }
+    sum.min(1.0)
+}
+
+/// Statistics on the results of the dice across a set of rolls, for
+/// settling arguments about whose dice are cursed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LuckStats {
+    /// Results for each size of die rolled, smallest first.
+    dice: Vec<FaceCounts>,
+
+    /// Number of rolls made.
+    rolls: usize,
+
+    /// Number of checks with a natural 20.
+    nat_20s: u64,
+
+    /// Number of checks with a natural 1.
+    nat_1s: u64,
+}
+
+impl LuckStats {
+    /// Gather statistics from the provided rolls. Every result rolled is
+    /// counted, including those dropped, rerolled or exploded into.
+    pub fn new(outcomes: &[RollOutcome]) -> Self {
+        let mut stats = Self {
+            rolls: outcomes.len(),
+            ..Default::default()
+        };
+        for outcome in outcomes {
+            match natural_d20(&outcome.results) {
+                Some(20) => stats.nat_20s += 1,
+                Some(1) => stats.nat_1s += 1,
+                _ => {}
+            }
+
+            for dice in &outcome.results {
+                let size = dice.dice.size;
+                let i = match stats.dice.binary_search_by_key(&size, |d| d.size)
+                {
+                    Ok(i) => i,
+                    Err(i) => {
+                        stats.dice.insert(i, FaceCounts::new(size));
+                        i
+                    }
+                };
+                for die in &dice.results {
+                    for &value in die.rolls.iter().chain(&die.rerolled) {
+                        stats.dice[i].add(value);
+                    }
+                }
+            }
+        }
+        stats
+    }
+
+    /// Results for each size of die rolled, smallest first.
+    pub fn dice(&self) -> &[FaceCounts] {
+        &self.dice
+    }
+
+    /// Results for dice of the provided size, if any were rolled.
+    pub fn die(&self, size: u32) -> Option<&FaceCounts> {
+        self.dice.iter().find(|d| d.size == size)
+    }
+
+    pub fn nat_20s(&self) -> u64 {
+        self.nat_20s
+    }
+
+    pub fn nat_1s(&self) -> u64 {
+        self.nat_1s
+    }
+
+    /// Summarise the statistics as plain text, for export.
+    pub fn format(&self) -> String {
+        let mut lines = vec![
+            format!("Rolls: {}", self.rolls),
+            format!("Natural 20s: {}", self.nat_20s),
+            format!("Natural 1s: {}", self.nat_1s),
+        ];
+
+        if let Some(d20) = self.die(20) {
+            lines.push(String::new());
+            lines.push("d20 results:".to_string());
+            for (face, n) in (1..).zip(d20.counts()) {
+                lines.push(format!("{face:>2}: {n}"));
+            }
+        }
+
+        for die in &self.dice {
+            lines.push(String::new());
+            lines.push(format!("d{}: {} rolled", die.size, die.total()));
+            lines.push(format!(
+                "Average {:.2}, expected {:.2}",
+                die.average(),
+                die.expected()
+            ));
+            lines.push(format!(
+                "Chi-squared {:.2}, p = {:.3}: {}",
+                die.chi_squared(),
+                die.p_value(),
+                die.verdict()
+            ));
+        }
+
+        lines.join("\n") + "\n"
+    }
+}
+
+/// Format the roll log can be exported in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    Csv,
+    Json,
+}
+
+impl LogFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+        }
+    }
+}
+
+/// A single roll in the exported roll log.
+#[derive(serde::Serialize)]
+struct RollRecord {
+    /// Time the roll was made, in seconds since the Unix epoch.
+    timestamp: u64,
+    label: String,
+    expression: String,
+    dice: String,
+    total: f64,
+}
+
+impl RollRecord {
+    const CSV_HEADER: &str = "timestamp,label,expression,dice,total";
+
+    fn format_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{}",
+            self.timestamp,
+            csv_field(&self.label),
+            csv_field(&self.expression),
+            csv_field(&self.dice),
+            self.total
+        )
+    }
+}
+
+/// Quote a CSV field if it contains a delimiter, quote or line break.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Format a log of rolls for export, one record per roll, oldest first.
+pub fn export_rolls(
+    outcomes: &[RollOutcome],
+    format: LogFormat,
+) -> Result<String, String> {
+    let records = outcomes.iter().map(RollOutcome::record);
+    match format {
+        LogFormat::Csv => {
+            let mut lines = vec![RollRecord::CSV_HEADER.to_string()];
+            lines.extend(records.map(|r| r.format_csv()));
+            Ok(lines.join("\n") + "\n")
+        }
+        LogFormat::Json => {
+            serde_json::to_string_pretty(&records.collect::<Vec<RollRecord>>())
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Error describing why a roll expression couldn't be parsed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    /// Byte offset in the text at which parsing failed.
+    pub offset: usize,
+
+    /// Length in bytes of the text at the offset which couldn't be parsed.
+    /// Zero if the roll ended early.
+    pub length: usize,
+
+    /// Description of what was expected at the offset.
+    pub expected: String,
+
+    pub message: String,
+}
+
+/// Failure while parsing a roll, located by the amount of text remaining so
+/// that it can be converted to a byte offset once parsing is complete.
+#[derive(Debug)]
+struct Failure {
+    remaining: usize,
+    expected: String,
+    found: Option<char>,
+}
+
+impl Failure {
+    /// Convert to a parse error with a byte offset in the provided text,
+    /// which is length characters long.
+    fn locate(self, text: &str, length: usize) -> ParseError {
+        let index = length - self.remaining;
+        let offset = text
+            .char_indices()
+            .nth(index)
+            .map(|(i, _)| i)
+            .unwrap_or(text.len());
+        let found = match self.found {
+            Some(c) => format!("'{c}'"),
+            None => "end of roll".to_string(),
+        };
+        ParseError {
+            offset,
+            length: self.found.map(|c| c.len_utf8()).unwrap_or(0),
+            message: format!("Expected {}, found {found}.", self.expected),
+            expected: self.expected,
+        }
+    }
+}
+
+/// Result of parsing a prefix of some text, with the remaining text.
+type Parsed<'a, T> = Result<(&'a [char], T), Failure>;
+
+/// Create a failure at the start of the provided text, ignoring whitespace.
+fn failure(text: &[char], expected: &str) -> Failure {
+    let text = trim_whitespace(text);
+    Failure {
+        remaining: text.len(),
+        expected: expected.to_string(),
+        found: text.first().copied(),
+    }
+}
+
+fn take_leading_int(text: &[char]) -> Option<(&[char], u32)> {
+    let mut started = false;
+    let mut val = 0;
+    for (i, c) in text.iter().enumerate() {
+        match c {
+            _ if c.is_whitespace() && !started => (),
+            v if v.is_digit(10) => {
+                val *= 10;
+                val += v.to_digit(10).unwrap();
+                started = true;
+            }
+            _ if started => return Some((&text[i..], val)),
+            _ => return None,
+        }
+    }
+
+    if started { Some((&[], val)) } else { None }
+}
+
+fn next_char(text: &[char]) -> Option<(&[char], char)> {
+    let mut stripped = text;
+    while !stripped.is_empty() && stripped[0].is_whitespace() {
+        stripped = &stripped[1..];
+    }
+
+    if !stripped.is_empty() {
+        Some((&stripped[1..], stripped[0]))
+    } else {
+        None
+    }
+}
+
+fn take_leading_number(text: &[char]) -> Option<(&[char], f64)> {
+    let mut num = String::new();
+    let mut text = text;
+    while let Some((rest, c)) = next_char(text) {
+        match c {
+            '.' => {
+                if !num.contains('.') {
+                    num.push('.');
+                } else {
+                    return num.parse().ok().map(|v| (text, v));
+                }
+            }
+            _ if c.is_digit(10) => num.push(c),
+            _ => return num.parse().ok().map(|v| (text, v)),
+        }
+
+        text = rest;
+    }
+    num.parse().ok().map(|v| (text, v))
+}
+
+fn expect(expected: char, text: &[char]) -> Option<&[char]> {
+    let (rest, actual) = next_char(text)?;
+    if actual == expected { Some(rest) } else { None }
+}
+
+fn trim_whitespace(mut text: &[char]) -> &[char] {
+    while !text.is_empty() && text[0].is_whitespace() {
+        text = &text[1..];
+    }
+    text
+}
+
+/// Take a leading integer if present, otherwise returning the default.
+fn take_count(text: &[char], default: u32) -> (&[char], u32) {
+    take_leading_int(text).unwrap_or((text, default))
+}
+
+/// Parse a suffix selecting which dice to keep, e.g. a, k3, kl2 or dh.
+fn parse_roll_suff(text: &[char]) -> Option<(&[char], RollSuff)> {
+    let (text, c) = next_char(text)?;
+    match c {
+        'a' => Some((text, RollSuff::Advantage)),
+        'd' => {
+            if let Some(text) = expect('h', text) {
+                let (text, n) = take_count(text, 1);
+                Some((text, RollSuff::DropHighest(n)))
+            } else if let Some(text) = expect('l', text) {
+                let (text, n) = take_count(text, 1);
+                Some((text, RollSuff::DropLowest(n)))
+            } else {
+                Some((text, RollSuff::Disadvantage))
+            }
+        }
+        'k' => {
+            if let Some(text) = expect('l', text) {
+                let (text, n) = take_count(text, 1);
+                Some((text, RollSuff::KeepLowest(n)))
+            } else {
+                let text = expect('h', text).unwrap_or(text);
+                let (text, n) = take_count(text, 1);
+                Some((text, RollSuff::Keep(n)))
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Parse a comparison against a target number, e.g. <2, >=7 or 1.
+fn parse_compare(text: &[char]) -> Option<(&[char], Compare)> {
+    let (rest, c) = next_char(text)?;
+    let (text, or_equal) = match c {
+        '<' | '>' => match expect('=', rest) {
+            Some(rest) => (rest, true),
+            None => (rest, false),
+        },
+        '=' => (rest, false),
+        _ => (text, false),
+    };
+    let (text, n) = take_leading_int(text)?;
+    let compare = match (c, or_equal) {
+        ('<', false) => Compare::Lt(n),
+        ('<', true) => Compare::Le(n),
+        ('>', false) => Compare::Gt(n),
+        ('>', true) => Compare::Ge(n),
+        _ => Compare::Eq(n),
+    };
+    Some((text, compare))
+}
+
+/// Parse a reroll suffix, e.g. r<2 to reroll once or rr1 to reroll until.
+fn parse_reroll(text: &[char]) -> Option<(&[char], Reroll)> {
+    let text = expect('r', text)?;
+    let (text, once) = match expect('r', text) {
+        Some(text) => (text, false),
+        None => (text, true),
+    };
+    let (text, when) = parse_compare(text)?;
+    Some((text, Reroll { once, when }))
+}
+
+/// Parse a rule for counting successes in a pool if present, e.g. >=7 or
+/// >=7f1 to also subtract a success for each 1.
+fn parse_count(text: &[char]) -> Parsed<'_, Option<Count>> {
+    match next_char(text) {
+        Some((_, '<' | '>' | '=')) => (),
+        _ => return Ok((text, None)),
+    }
+
+    let (rest, success) =
+        parse_compare(text).ok_or_else(|| failure(text, "target number"))?;
+    let Some(rest) = rest.strip_prefix(&['f'][..]) else {
+        let count = Count {
+            success,
+            failure: None,
+        };
+        return Ok((rest, Some(count)));
+    };
+    let (rest, failure_rule) =
+        parse_compare(rest).ok_or_else(|| failure(rest, "failure target"))?;
+    let count = Count {
+        success,
+        failure: Some(failure_rule),
+    };
+    Ok((rest, Some(count)))
+}
+
+/// Parse a group of dice, e.g. 4d6k3 or d20, if the text begins with one.
+fn parse_dice(text: &[char]) -> Parsed<'_, Option<Dice>> {
+    let (text, quantity) = match take_leading_int(text) {
+        Some((rest, quantity)) => match expect('d', rest) {
+            Some(rest) => (rest, quantity),
+            None => return Ok((text, None)),
+        },
+        None => match expect('d', text) {
+            Some(rest) => (rest, 1),
+            None => return Ok((text, None)),
+        },
+    };
+    let (mut text, size) = match take_leading_int(text) {
+        Some((_, 0)) | None => return Err(failure(text, "die size")),
+        Some(parsed) => parsed,
+    };
+    let mut dice = Dice::new(quantity, size);
+
+    // Suffixes may appear in any order. Later suffixes of the same kind
+    // override earlier ones. They must follow the dice directly, to avoid
+    // reading the start of a damage type like acid as a suffix.
+    while text.first().is_some_and(|c| !c.is_whitespace()) {
+        if let Some(rest) = expect('!', text) {
+            text = rest;
+            dice.explode = true;
+        } else if let Some((rest, reroll)) = parse_reroll(text) {
+            text = rest;
+            dice.reroll = Some(reroll);
+        } else if let Some((rest, suff)) = parse_roll_suff(text) {
+            text = rest;
+            dice.suff = suff;
+        } else {
+            break;
+        }
+    }
+
+    let (text, count) = parse_count(text)?;
+    if count.is_some() && dice.suff != RollSuff::None {
+        // Keeping dice is based on their totals, which pools don't have.
+        return Err(failure(text, "a pool without keep or drop"));
+    }
+    dice.count = count;
+
+    Ok((text, Some(dice)))
+}
+
+/// Parse a reference to a sheet variable, e.g. @dex or @skill.Stealth.
+fn parse_var(text: &[char]) -> Option<(&[char], Var)> {
+    let text = expect('@', text)?;
+    let length = text
+        .iter()
+        .position(|c| !(c.is_alphanumeric() || *c == '_' || *c == '.'))
+        .unwrap_or(text.len());
+    if length == 0 {
+        return None;
+    }
+
+    let var = Var {
+        name: text[..length].iter().collect(),
+        value: None,
+    };
+    Some((&text[length..], var))
+}
+
+/// Types of damage which may follow a term in a roll.
+const DAMAGE_TYPES: &[&str] = &[
+    "acid",
+    "bleed",
+    "bludgeoning",
+    "chaotic",
+    "cold",
+    "electricity",
+    "evil",
+    "fire",
+    "force",
+    "good",
+    "lawful",
+    "mental",
+    "negative",
+    "piercing",
+    "poison",
+    "positive",
+    "precision",
+    "slashing",
+    "sonic",
+    "spirit",
+    "vitality",
+    "void",
+];
+
+/// Parse the type of damage following a term if present, e.g. fire.
+fn parse_damage_type(text: &[char]) -> Option<(&[char], String)> {
+    let text = trim_whitespace(text);
+    let length = text
+        .iter()
+        .position(|c| !c.is_alphabetic())
+        .unwrap_or(text.len());
+    let kind = text[..length].iter().collect::<String>().to_lowercase();
+    if DAMAGE_TYPES.contains(&kind.as_str()) {
+        Some((&text[length..], kind))
+    } else {
+        None
+    }
+}
+
+/// Parse a single operand, optionally followed by a damage type.
+fn parse_primary(text: &[char]) -> Parsed<'_, Expr> {
+    let (rest, expr) = parse_operand(text)?;
+    match parse_damage_type(rest) {
+        Some((rest, kind)) => Ok((rest, Expr::Typed(Box::new(expr), kind))),
+        None => Ok((rest, expr)),
+    }
+}
+
+/// Parse a single operand: a group of dice, a number, a sheet variable, a
+/// negated operand or a parenthesised expression.
+fn parse_operand(text: &[char]) -> Parsed<'_, Expr> {
+    const OPERAND: &str = "dice, number or variable";
+    let Some((rest, c)) = next_char(text) else {
+        return Err(failure(text, OPERAND));
+    };
+    match c {
+        '@' => {
+            let (rest, var) = parse_var(text)
+                .ok_or_else(|| failure(rest, "variable name"))?;
+            Ok((rest, Expr::Var(var)))
+        }
+        '(' => {
+            let (rest, expr) = parse_expr(rest)?;
+            let rest = expect(')', rest).ok_or_else(|| failure(rest, "')'"))?;
+            Ok((rest, expr))
+        }
+        '-' => {
+            let (rest, expr) = parse_primary(rest)?;
+            Ok((rest, Expr::Neg(Box::new(expr))))
+        }
+        _ => {
+            if let (rest, Some(dice)) = parse_dice(text)? {
+                Ok((rest, Expr::Dice(dice)))
+            } else if let Some((rest, value)) = take_leading_number(text) {
+                Ok((rest, Expr::Const(value)))
+            } else {
+                Err(failure(text, OPERAND))
+            }
+        }
+    }
+}
+
+/// Parse a chain of operands joined by operators of at least the provided
+/// precedence, e.g. 2d6 * 2 / 3 for multiplicative precedence.
+fn parse_binary(text: &[char], precedence: u8) -> Parsed<'_, Expr> {
+    let (mut text, mut expr) = if precedence >= RollOp::Mul.precedence() {
+        parse_primary(text)?
+    } else {
+        parse_binary(text, precedence + 1)?
+    };
+
+    while let Some((rest, c)) = next_char(text) {
+        let op = match RollOp::from(c) {
+            Some(op) if op.precedence() == precedence => op,
+            _ => break,
+        };
+
+        let (rest, operand) = if precedence >= RollOp::Mul.precedence() {
+            parse_primary(rest)?
+        } else {
+            parse_binary(rest, precedence + 1)?
+        };
+        text = rest;
+        expr = Expr::Mod(Box::new(expr), RollMod::new(op, operand));
+    }
+
+    Ok((text, expr))
+}
+
+fn parse_expr(text: &[char]) -> Parsed<'_, Expr> {
+    parse_binary(text, RollOp::Add.precedence())
+}
+
+/// Expect the provided keyword, ignoring case and leading whitespace.
+fn expect_keyword<'a>(keyword: &str, text: &'a [char]) -> Option<&'a [char]> {
+    let mut text = trim_whitespace(text);
+    for expected in keyword.chars() {
+        let (first, rest) = text.split_first()?;
+        if !first.eq_ignore_ascii_case(&expected) {
+            return None;
+        }
+        text = rest;
+    }
+    Some(text)
+}
+
+/// Parse the DC of a check following a roll expression, e.g. vs DC 15, if
+/// present.
+fn parse_dc(text: &[char]) -> Parsed<'_, Option<i64>> {
+    let Some(text) = expect_keyword("vs", text) else {
+        return Ok((text, None));
+    };
+    let text = expect_keyword("dc", text).unwrap_or(text);
+    let (rest, dc) =
+        take_leading_int(text).ok_or_else(|| failure(text, "DC"))?;
+    Ok((rest, Some(dc as i64)))
+}
+
+/// Parse the extra dice rolled on a critical hit following a roll expression,
+/// e.g. crit 1d10, if present.
+fn parse_crit(text: &[char]) -> Parsed<'_, Option<Expr>> {
+    match expect_keyword("crit", text) {
+        Some(rest) => {
+            let (rest, expr) = parse_expr(rest)?;
+            Ok((rest, Some(expr)))
+        }
+        None => Ok((text, None)),
+    }
+}
+
+fn parse_roll_chars(text: &[char]) -> Result<Roll, Failure> {
+    let (rest, expr) = parse_expr(text)?;
+    let (rest, crit) = parse_crit(rest)?;
+    let (rest, dc) = parse_dc(rest)?;
+    if trim_whitespace(rest).is_empty() {
+        Ok(Roll {
+            expr,
+            crit,
+            dc,
+            label: None,
+            suppressed: Vec::new(),
+        })
+    } else if dc.is_some() {
+        Err(failure(rest, "end of roll"))
+    } else if crit.is_some() {
+        Err(failure(rest, "operator or vs DC"))
+    } else {
+        Err(failure(rest, "operator, crit or vs DC"))
+    }
+}
+
+fn parse_roll(text: &str) -> Result<Roll, ParseError> {
+    let chars = text.chars().collect::<Vec<char>>();
+    parse_roll_chars(&chars).map_err(|f| f.locate(text, chars.len()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn dice(quantity: u32, size: u32) -> Expr {
+        Expr::Dice(Dice::new(quantity, size))
+    }
+
+    fn op(lhs: Expr, op: RollOp, rhs: Expr) -> Expr {
+        Expr::Mod(Box::new(lhs), RollMod::new(op, rhs))
+    }
+
+    /// (4d6k3 + 10) * 10.1
+    fn keep_roll() -> Roll {
+        let dice = Expr::Dice(Dice {
+            suff: RollSuff::Keep(3),
+            ..Dice::new(4, 6)
+        });
+        Roll {
+            expr: op(
+                op(dice, RollOp::Add, Expr::Const(10.0)),
+                RollOp::Mul,
+                Expr::Const(10.1),
+            ),
+            crit: None,
+            dc: None,
+            label: None,
+            suppressed: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_format_add_mod() {
+        let modifier = RollMod::new(RollOp::Add, Expr::Const(3.0));
+        assert_eq!(modifier.format_with(false), "+ 3");
+    }
+
+    #[test]
+    fn test_format_sub_mod() {
+        let modifier = RollMod::new(RollOp::Sub, Expr::Const(3.2));
+        assert_eq!(modifier.format_with(false), "- 3.2");
+    }
+
+    #[test]
     fn test_format_mul_mod() {
-        let modifier = RollMod {
-            op: RollOp::Mul,
-            amount: 123.0,
-        };
-        assert_eq!(modifier.format(), "* 123");
+        let modifier = RollMod::new(RollOp::Mul, Expr::Const(123.0));
+        assert_eq!(modifier.format_with(false), "* 123");
     }
 
     #[test]
     fn test_format_div_mod() {
-        let modifier = RollMod {
-            op: RollOp::Div,
-            amount: 0.125,
-        };
-        assert_eq!(modifier.format(), "/ 0.125");
+        let modifier = RollMod::new(RollOp::Div, Expr::Const(0.125));
+        assert_eq!(modifier.format_with(false), "/ 0.125");
     }
 
     #[test]
@@ -384,49 +2502,20 @@ mod test {
 
     #[test]
     fn test_format_roll() {
-        let roll = Roll {
-            quantity: 4,
-            size: 6,
-            suff: RollSuff::Keep(3),
-            mods: vec![
-                RollMod {
-                    op: RollOp::Add,
-                    amount: 10.0,
-                },
-                RollMod {
-                    op: RollOp::Mul,
-                    amount: 10.1,
-                },
-            ],
-        };
-        assert_eq!(roll.format(), "4d6k3 + 10 * 10.1");
+        let roll = keep_roll();
+        assert_eq!(roll.format(), "(4d6k3 + 10) * 10.1");
     }
 
     #[test]
     fn test_parse_roll() {
-        let roll = Roll {
-            quantity: 4,
-            size: 6,
-            suff: RollSuff::Keep(3),
-            mods: vec![
-                RollMod {
-                    op: RollOp::Add,
-                    amount: 10.0,
-                },
-                RollMod {
-                    op: RollOp::Mul,
-                    amount: 10.1,
-                },
-            ],
-        };
+        let roll = keep_roll();
         assert_eq!(parse_roll(roll.format().as_str()).unwrap(), roll);
     }
 
     #[test]
     fn test_parse_keep_suff() {
-        let expected: (&[char], RollSuff, Vec<RollMod>) =
-            (&[], RollSuff::Keep(8), Vec::new());
-        assert_eq!(parse_roll_suff_mods(&['k', '8']).unwrap(), expected);
+        let expected: (&[char], RollSuff) = (&[], RollSuff::Keep(8));
+        assert_eq!(parse_roll_suff(&['k', '8']).unwrap(), expected);
     }
 
     #[test]
@@ -453,4 +2542,685 @@ mod test {
     fn test_trim_whitespace() {
         assert_eq!(trim_whitespace(&[' ', ' ', 'd']), &['d']);
     }
+
+    #[test]
+    fn test_parse_precedence() {
+        let expected = op(
+            op(
+                dice(2, 6),
+                RollOp::Add,
+                op(dice(1, 4), RollOp::Mul, dice(1, 8)),
+            ),
+            RollOp::Sub,
+            Expr::Const(3.0),
+        );
+        assert_eq!(parse_roll("2d6 + d4 * d8 - 3").unwrap().expr, expected);
+    }
+
+    #[test]
+    fn test_parse_parentheses() {
+        let roll = parse_roll("(1d8 + 4) * 2").unwrap();
+        let expected = op(
+            op(dice(1, 8), RollOp::Add, Expr::Const(4.0)),
+            RollOp::Mul,
+            Expr::Const(2.0),
+        );
+        assert_eq!(roll.expr, expected);
+        assert_eq!(roll.format(), "(d8 + 4) * 2");
+    }
+
+    #[test]
+    fn test_format_right_grouping() {
+        for text in ["10 - (d4 + 2)", "d20 / (2 * 2)", "-(d6 + 1) + 2"] {
+            assert_eq!(parse_roll(text).unwrap().format(), text);
+        }
+    }
+
+    #[test]
+    fn test_parse_trailing_garbage() {
+        assert!(parse_roll("d20 + ").is_err());
+        assert!(parse_roll("d20 3").is_err());
+        assert!(parse_roll("(d20 + 3").is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = parse_roll("d20 + ").unwrap_err();
+        assert_eq!(error.offset, 6);
+        assert_eq!(error.length, 0);
+        assert_eq!(error.expected, "dice, number or variable");
+        assert_eq!(
+            error.message,
+            "Expected dice, number or variable, found end of roll."
+        );
+
+        let error = parse_roll("(d20 + 3 x").unwrap_err();
+        assert_eq!(error.offset, 10);
+        assert_eq!(error.expected, "dice, number or variable");
+
+        let error = parse_roll("(d20 + 3] * 2").unwrap_err();
+        assert_eq!((error.offset, error.length), (8, 1));
+        assert_eq!(error.message, "Expected ')', found ']'.");
+
+        let error = parse_roll("2d + 1").unwrap_err();
+        assert_eq!((error.offset, error.expected.as_str()), (3, "die size"));
+
+        let error = parse_roll("d20 vs DC").unwrap_err();
+        assert_eq!((error.offset, error.expected.as_str()), (9, "DC"));
+
+        // Offsets are in bytes, so count multi-byte characters fully.
+        let error = parse_roll("d20 + é").unwrap_err();
+        assert_eq!((error.offset, error.length), (6, 2));
+        let error = parse_roll("d20 + 1 é").unwrap_err();
+        assert_eq!(error.expected, "operator, crit or vs DC");
+    }
+
+    #[test]
+    fn test_resolve_terms() {
+        let outcome = parse_roll("2d1 + 3d1 * 2 - 1")
+            .unwrap()
+            .resolve(&DiceRng::default());
+        assert_eq!(outcome.value, 7.0);
+        assert_eq!(outcome.format_results(), "2d1: 1, 1; 3d1: 1, 1, 1");
+    }
+
+    #[test]
+    fn test_degree_thresholds() {
+        let cases = [
+            (5.0, Degree::CriticalFailure),
+            (6.0, Degree::Failure),
+            (14.0, Degree::Failure),
+            (15.0, Degree::Success),
+            (24.0, Degree::Success),
+            (25.0, Degree::CriticalSuccess),
+        ];
+        for (total, expected) in cases {
+            assert_eq!(Degree::of(total, Some(10), 15), expected);
+        }
+    }
+
+    #[test]
+    fn test_degree_natural_results() {
+        assert_eq!(Degree::of(14.0, Some(20), 15), Degree::Success);
+        assert_eq!(Degree::of(25.0, Some(20), 15), Degree::CriticalSuccess);
+        assert_eq!(Degree::of(4.0, Some(20), 15), Degree::Failure);
+        assert_eq!(Degree::of(15.0, Some(1), 15), Degree::Failure);
+        assert_eq!(Degree::of(14.0, Some(1), 15), Degree::CriticalFailure);
+        assert_eq!(Degree::of(30.0, Some(1), 15), Degree::Success);
+    }
+
+    #[test]
+    fn test_parse_dc() {
+        let roll = parse_roll("d20 + 7 vs DC 18").unwrap();
+        assert_eq!(roll.dc, Some(18));
+        assert_eq!(roll.format(), "d20 + 7 vs DC 18");
+        assert_eq!(parse_roll("d20 vs 12").unwrap().dc, Some(12));
+        assert!(parse_roll("d20 vs").is_err());
+    }
+
+    #[test]
+    fn test_resolve_check() {
+        // A d1 is never a natural 20 or 1, so only the thresholds apply.
+        let outcome = Roll::new(1, 1)
+            .plus(14.0)
+            .against(15)
+            .resolve(&DiceRng::default());
+        assert_eq!(outcome.degree(), Some(Degree::Success));
+        assert_eq!(Roll::new(1, 1).resolve(&DiceRng::default()).degree(), None);
+    }
+
+    #[test]
+    fn test_natural_d20() {
+        let outcome = |suff, values: Vec<u32>| {
+            let dice = Dice {
+                suff,
+                ..Dice::new(values.len() as u32, 20)
+            };
+            let mut results: Vec<DieResult> = values
+                .into_iter()
+                .map(|v| DieResult {
+                    rolls: vec![v],
+                    ..Default::default()
+                })
+                .collect();
+            dice.select(&mut results);
+            DiceOutcome { dice, results }
+        };
+        assert_eq!(natural_d20(&[outcome(RollSuff::None, vec![20])]), Some(20));
+        assert_eq!(
+            natural_d20(&[outcome(RollSuff::Advantage, vec![1, 20])]),
+            Some(20)
+        );
+        assert_eq!(natural_d20(&[outcome(RollSuff::None, vec![1, 20])]), None);
+    }
+
+    #[test]
+    fn test_format_roll_suffs_keep_drop() {
+        assert_eq!(RollSuff::KeepLowest(2).format(), "kl2");
+        assert_eq!(RollSuff::DropHighest(1).format(), "dh1");
+        assert_eq!(RollSuff::DropLowest(3).format(), "dl3");
+    }
+
+    #[test]
+    fn test_parse_dice_suffs() {
+        let cases = [
+            ("4d6kl2", "4d6kl2"),
+            ("4d6kh3", "4d6k3"),
+            ("4d6dl", "4d6dl1"),
+            ("2d20dh", "2d20dh1"),
+            ("d6!", "d6!"),
+            ("2d6r<2", "2d6r<2"),
+            ("d20rr1", "d20rr1"),
+            ("4d6!rr<=2k3", "4d6rr<=2!k3"),
+            ("d20d", "d20d"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_roll(text).unwrap().format(), expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_reroll() {
+        let expected = Reroll {
+            once: true,
+            when: Compare::Lt(2),
+        };
+        assert_eq!(parse_reroll(&['r', '<', '2']).unwrap().1, expected);
+        let expected = Reroll {
+            once: false,
+            when: Compare::Eq(1),
+        };
+        assert_eq!(parse_reroll(&['r', 'r', '1']).unwrap().1, expected);
+        assert!(parse_reroll(&['r']).is_none());
+    }
+
+    #[test]
+    fn test_select_dice() {
+        let values = [3, 6, 1, 4];
+        let cases = [
+            (RollSuff::Keep(2), 10),
+            (RollSuff::KeepLowest(2), 4),
+            (RollSuff::DropHighest(1), 8),
+            (RollSuff::DropLowest(1), 13),
+            (RollSuff::Advantage, 6),
+            (RollSuff::Disadvantage, 1),
+        ];
+        for (suff, expected) in cases {
+            let dice = Dice {
+                suff,
+                ..Dice::new(4, 6)
+            };
+            let mut results: Vec<DieResult> = values
+                .iter()
+                .map(|&v| DieResult {
+                    rolls: vec![v],
+                    ..Default::default()
+                })
+                .collect();
+            dice.select(&mut results);
+            assert_eq!(dice.total(&results), expected);
+        }
+    }
+
+    #[test]
+    fn test_resolve_exploding_and_rerolls() {
+        // Every d1 result explodes or matches the reroll, so these stop at
+        // the repeat limit.
+        let outcome = parse_roll("d1!").unwrap().resolve(&DiceRng::default());
+        assert_eq!(outcome.value, MAX_REPEATS as f64 + 1.0);
+        let outcome = parse_roll("d1rr1").unwrap().resolve(&DiceRng::default());
+        assert_eq!(outcome.results[0].results[0].rerolled.len(), MAX_REPEATS);
+        let outcome = parse_roll("d1r1").unwrap().resolve(&DiceRng::default());
+        assert_eq!(outcome.format_results(), "1->1");
+    }
+
+    #[test]
+    fn test_format_die_result() {
+        let die = DieResult {
+            rolls: vec![6, 6, 2],
+            rerolled: vec![1],
+            dropped: false,
+        };
+        assert_eq!(die.format(), "1->6!+6!+2");
+        let die = DieResult {
+            rolls: vec![3],
+            rerolled: Vec::new(),
+            dropped: true,
+        };
+        assert_eq!(die.format(), "[3]");
+    }
+
+    #[test]
+    fn test_seeded_rolls_repeat() {
+        let roll = parse_roll("4d6!k3 + 2d20rr1 * d8").unwrap();
+        let a = roll.clone().resolve(&DiceRng::seeded(42));
+        let b = roll.resolve(&DiceRng::seeded(42));
+        assert_eq!(a.format_results(), b.format_results());
+        assert_eq!(a.value, b.value);
+    }
+
+    #[test]
+    fn test_replay_roll() {
+        let rng = DiceRng::seeded(7);
+        let roll = parse_roll("10d20 + 3d6!").unwrap();
+        let outcomes: Vec<RollOutcome> =
+            (0..5).map(|_| roll.clone().resolve(&rng)).collect();
+        for outcome in &outcomes {
+            assert!(outcome.verify());
+            assert_eq!(
+                outcome.replay().format_results(),
+                outcome.format_results()
+            );
+        }
+
+        let mut tampered = outcomes[2].clone();
+        tampered.value += 1.0;
+        assert!(!tampered.verify());
+    }
+
+    #[test]
+    fn test_serialize_outcome() {
+        let outcome = parse_roll("2d6! + @dex vs DC 10")
+            .unwrap()
+            .bind(|_| Some(2))
+            .unwrap()
+            .resolve(&DiceRng::seeded(3));
+        let json = serde_json::to_string(&outcome).unwrap();
+        let loaded: RollOutcome = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.format_roll(), outcome.format_roll());
+        assert_eq!(loaded.format_results(), outcome.format_results());
+        assert_eq!(loaded.degree(), outcome.degree());
+        assert_eq!(loaded.timestamp(), outcome.timestamp());
+        assert!(loaded.verify());
+    }
+
+    #[test]
+    fn test_parse_pool() {
+        let roll = parse_roll("6d10>=7").unwrap();
+        assert_eq!(roll.format(), "6d10>=7");
+        for text in ["6d10>=7f1", "6d10=10", "4d6!>5f<2 + 1"] {
+            assert_eq!(parse_roll(text).unwrap().format(), text);
+        }
+        let error = parse_roll("6d10>=").unwrap_err();
+        assert_eq!(error.expected, "target number");
+        let error = parse_roll("6d10>=7f").unwrap_err();
+        assert_eq!(error.expected, "failure target");
+        assert!(parse_roll("6d10k3>=7").is_err());
+    }
+
+    #[test]
+    fn test_resolve_pool() {
+        let outcome =
+            parse_roll("6d1>=1").unwrap().resolve(&DiceRng::default());
+        assert_eq!(outcome.value, 6.0);
+        assert_eq!(outcome.format_value(), "6 successes");
+
+        let outcome =
+            parse_roll("3d1>=2f1").unwrap().resolve(&DiceRng::default());
+        assert_eq!(outcome.format_value(), "-3 successes");
+
+        let dice = parse_dice(&"3d10!>=7".chars().collect::<Vec<char>>())
+            .unwrap()
+            .1
+            .unwrap();
+        let results = [vec![10, 8], vec![3], vec![7]]
+            .into_iter()
+            .map(|rolls| DieResult {
+                rolls,
+                ..Default::default()
+            })
+            .collect::<Vec<DieResult>>();
+        assert_eq!(dice.total(&results), 3);
+    }
+
+    #[test]
+    fn test_pool_distribution() {
+        let dist = parse_roll("6d10>=7").unwrap().distribution().unwrap();
+        assert_close(dist.mean(), 2.4);
+        assert_close(chance_of(&dist, 6.0), 0.4f64.powi(6));
+
+        let dist = parse_roll("6d10>=7f1").unwrap().distribution().unwrap();
+        assert_close(dist.mean(), 1.8);
+        assert_eq!(dist.min(), -6.0);
+    }
+
+    #[test]
+    fn test_parse_crit() {
+        let roll = parse_roll("2d8 + 4 crit 1d10").unwrap();
+        assert_eq!(roll.format(), "2d8 + 4 crit d10");
+        assert_eq!(roll.distribution().unwrap().max(), 20.0);
+        assert!(parse_roll("2d8 crit").is_err());
+        assert!(parse_roll("d20 crit d6 vs 15").is_ok());
+    }
+
+    #[test]
+    fn test_critical() {
+        let roll = parse_roll("2d8 + 4 crit 1d10").unwrap();
+        let critical = roll.clone().critical(CritRule::DoubleTotal);
+        assert_eq!(critical.format(), "(2d8 + 4) * 2 + d10");
+        assert_close(critical.distribution().unwrap().mean(), 13.0 * 2.0 + 5.5);
+
+        let critical = roll.critical(CritRule::DoubleDice);
+        assert_eq!(critical.format(), "4d8 + 4 + d10");
+        assert_eq!(critical.distribution().unwrap().max(), 32.0 + 4.0 + 10.0);
+
+        let critical = Roll::new(1, 6).critical(CritRule::DoubleDice);
+        assert_eq!(critical.format(), "2d6");
+
+        // Only summed dice are doubled.
+        let critical = parse_roll("2d20k1 + 2d6 + 3d6>=5")
+            .unwrap()
+            .critical(CritRule::DoubleDice);
+        assert_eq!(critical.format(), "2d20k1 + 4d6 + 3d6>=5");
+    }
+
+    #[test]
+    fn test_parse_damage_types() {
+        for text in [
+            "2d6 slashing + d6 fire",
+            "(d8 + 4) piercing + 2d6 acid vs DC 10",
+            "-2 cold",
+            "2d6 * 2 fire",
+            "d8 + 4 bludgeoning crit d10 bludgeoning",
+        ] {
+            assert_eq!(parse_roll(text).unwrap().format(), text);
+        }
+        // Suffixes must directly follow the dice.
+        let roll = parse_roll("2d6 acid").unwrap();
+        assert_eq!(roll.format(), "2d6 acid");
+        assert_eq!(parse_roll("2d6 x 2").unwrap().format(), "2d6 * 2");
+        assert_eq!(parse_roll("2d20a").unwrap().format(), "2d20a");
+
+        // Only known damage types are accepted.
+        let error = parse_roll("d20 + 5 fier").unwrap_err();
+        assert_eq!(error.offset, 8);
+        assert_eq!(error.expected, "operator, crit or vs DC");
+        assert!(parse_roll("d20 d").is_err());
+    }
+
+    #[test]
+    fn test_damage_by_type() {
+        let outcome = parse_roll("2d1 slashing + d1 fire + 3")
+            .unwrap()
+            .resolve(&DiceRng::default());
+        assert!(outcome.has_damage_types());
+        assert_eq!(
+            outcome.damage(),
+            vec![("slashing".to_string(), 2.0), ("fire".to_string(), 4.0)]
+        );
+        assert_eq!(outcome.format_damage(), "2 slashing, 4 fire");
+
+        // Untyped terms join the next typed term, and scaling applies per
+        // type.
+        let outcome = parse_roll("(2d1 + 4 slashing + 3d1 fire) * 2")
+            .unwrap()
+            .resolve(&DiceRng::default());
+        assert_eq!(outcome.format_damage(), "12 slashing, 6 fire");
+        assert_eq!(outcome.format_value(), "18");
+
+        let outcome =
+            parse_roll("2d1 + 3").unwrap().resolve(&DiceRng::default());
+        assert!(!outcome.has_damage_types());
+        assert_eq!(outcome.format_damage(), "");
+    }
+
+    #[test]
+    fn test_defenses() {
+        let defenses = Defenses::parse(
+            "immune poison, resist fire 5, Weakness cold iron 3, resist all 1",
+        )
+        .unwrap();
+        assert_eq!(
+            defenses.format(),
+            "immune poison, resist fire 5, resist all 1, weak cold iron 3"
+        );
+        assert_eq!(defenses.apply("poison", 10.0), 0.0);
+        assert_eq!(defenses.apply("fire", 4.0), 0.0);
+        assert_eq!(defenses.apply("fire", 8.0), 3.0);
+        assert_eq!(defenses.apply("cold iron", 2.0), 4.0);
+        assert_eq!(defenses.apply("cold iron", 0.0), 0.0);
+        assert_eq!(defenses.apply("slashing", 6.0), 5.0);
+        assert!(Defenses::parse("resist fire").is_err());
+        assert!(Defenses::parse("vulnerable fire 2").is_err());
+        assert_eq!(Defenses::parse(" ").unwrap(), Defenses::default());
+
+        let outcome = parse_roll("6d1 slashing + 4d1 fire")
+            .unwrap()
+            .resolve(&DiceRng::default())
+            .with_defenses(
+                Defenses::parse("resist fire 5, weak slashing 2").unwrap(),
+            );
+        assert_eq!(outcome.format_damage(), "6->8 slashing, 4->0 fire");
+        assert_eq!(outcome.format_value(), "8");
+    }
+
+    #[test]
+    fn test_format_breakdown() {
+        let roll = Roll::new(1, 20)
+            .plus_labelled(3.0, "DEX")
+            .plus_labelled(5.0, "Trained")
+            .plus_labelled(-1.0, "Frightened")
+            .plus(2.0);
+        assert_eq!(roll.format(), "d20 + 3 + 5 - 1 + 2");
+        assert_eq!(
+            roll.format_breakdown(),
+            "DEX +3, Trained +5, Frightened -1"
+        );
+        assert_eq!(Roll::new(1, 20).plus(2.0).format_breakdown(), "");
+    }
+
+    #[test]
+    fn test_parse_variables() {
+        let roll = parse_roll("d20 + @dex + @skill.Stealth").unwrap();
+        assert_eq!(roll.format(), "d20 + @dex + @skill.Stealth");
+        assert!(parse_roll("d20 + @").is_err());
+    }
+
+    #[test]
+    fn test_bind_variables() {
+        let lookup = |name: &str| match name {
+            "dex" => Some(3),
+            "level" => Some(2),
+            _ => None,
+        };
+        let roll = parse_roll("d20 + @dex + @level vs DC 15")
+            .unwrap()
+            .bind(lookup)
+            .unwrap();
+        assert_eq!(
+            roll.format_resolved(),
+            "d20 + @dex + @level = d20 + 3 + 2 vs DC 15"
+        );
+        assert_eq!(roll.distribution().unwrap().min(), 6.0);
+
+        let outcome = roll.resolve(&DiceRng::default());
+        assert!((6.0..=25.0).contains(&outcome.value));
+
+        let unknown = parse_roll("d20 + @wis").unwrap().bind(lookup);
+        assert_eq!(unknown, Err("wis".to_string()));
+        assert_eq!(parse_roll("d20 + 1").unwrap().format_resolved(), "d20 + 1");
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    fn chance_of(dist: &Distribution, value: f64) -> f64 {
+        dist.0
+            .iter()
+            .find(|(v, _)| *v == value)
+            .map(|(_, p)| *p)
+            .unwrap_or(0.0)
+    }
+
+    #[test]
+    fn test_distribution_sum() {
+        let dist = parse_roll("2d6").unwrap().distribution().unwrap();
+        assert_eq!(dist.min(), 2.0);
+        assert_eq!(dist.max(), 12.0);
+        assert_close(dist.mean(), 7.0);
+        assert_close(chance_of(&dist, 7.0), 6.0 / 36.0);
+        assert_eq!(dist.percentile(0.5), 7.0);
+    }
+
+    #[test]
+    fn test_distribution_modifiers() {
+        let dist = parse_roll("(d8 + 4) * 2").unwrap().distribution().unwrap();
+        assert_eq!(dist.min(), 10.0);
+        assert_eq!(dist.max(), 24.0);
+        assert_close(dist.mean(), 17.0);
+        assert_close(dist.chance_at_least(20.0), 3.0 / 8.0);
+    }
+
+    #[test]
+    fn test_distribution_advantage() {
+        let dist = parse_roll("2d20a").unwrap().distribution().unwrap();
+        assert_close(chance_of(&dist, 20.0), 1.0 - (19.0f64 / 20.0).powi(2));
+        assert_close(chance_of(&dist, 1.0), 1.0 / 400.0);
+        let dist = parse_roll("2d20d").unwrap().distribution().unwrap();
+        assert_close(chance_of(&dist, 1.0), 1.0 - (19.0f64 / 20.0).powi(2));
+    }
+
+    #[test]
+    fn test_distribution_keep() {
+        // Compare against brute force enumeration of every 4d6 outcome.
+        let mut k3 = 0.0;
+        let mut kl2 = 0.0;
+        for a in 1..=6 {
+            for b in 1..=6 {
+                for c in 1..=6 {
+                    for d in 1..=6 {
+                        let mut dice = [a, b, c, d];
+                        dice.sort();
+                        k3 += (dice[1] + dice[2] + dice[3]) as f64;
+                        kl2 += (dice[0] + dice[1]) as f64;
+                    }
+                }
+            }
+        }
+        let n = 6.0f64.powi(4);
+        let dist = parse_roll("4d6k3").unwrap().distribution().unwrap();
+        assert_close(dist.mean(), k3 / n);
+        assert_close(dist.0.iter().map(|(_, p)| p).sum(), 1.0);
+        let dist = parse_roll("4d6dl").unwrap().distribution().unwrap();
+        assert_close(dist.mean(), k3 / n);
+        let dist = parse_roll("4d6kl2").unwrap().distribution().unwrap();
+        assert_close(dist.mean(), kl2 / n);
+    }
+
+    #[test]
+    fn test_distribution_reroll_and_explode() {
+        let dist = parse_roll("d6r1").unwrap().distribution().unwrap();
+        assert_close(chance_of(&dist, 1.0), 1.0 / 36.0);
+        assert_close(chance_of(&dist, 2.0), 7.0 / 36.0);
+        let dist = parse_roll("d6rr1").unwrap().distribution().unwrap();
+        assert_close(chance_of(&dist, 2.0), 1.0 / 5.0);
+        let dist = parse_roll("d6!").unwrap().distribution().unwrap();
+        assert_eq!(chance_of(&dist, 6.0), 0.0);
+        assert_close(chance_of(&dist, 8.0), 1.0 / 36.0);
+        assert_close(dist.mean(), 3.5 * 6.0 / 5.0);
+    }
+
+    #[test]
+    fn test_distribution_limit() {
+        for text in ["100d1000", "d1000 * d1000 * d1000", "100d100k50"] {
+            let error = parse_roll(text).unwrap().distribution().unwrap_err();
+            assert_eq!(error, TOO_MANY_OUTCOMES);
+        }
+        assert!(parse_roll("20d20").unwrap().distribution().is_ok());
+        assert!(parse_roll("10d20k5").unwrap().distribution().is_ok());
+    }
+
+    #[test]
+    fn test_distribution_buckets() {
+        let dist = parse_roll("d4").unwrap().distribution().unwrap();
+        assert_eq!(dist.buckets(10).len(), 4);
+        let dist = parse_roll("10d6").unwrap().distribution().unwrap();
+        let buckets = dist.buckets(10);
+        assert!(buckets.len() <= 10);
+        assert_eq!(buckets.first().unwrap().0, 10.0);
+        assert_eq!(buckets.last().unwrap().1, 60.0);
+        assert_close(buckets.iter().map(|b| b.2).sum(), 1.0);
+    }
+
+    #[test]
+    fn test_lower_gamma_ratio() {
+        // Chi-squared survival values for 1 and 2 degrees of freedom.
+        assert!(
+            (1.0 - lower_gamma_ratio(0.5, 3.841 / 2.0) - 0.05).abs() < 1e-4
+        );
+        assert!(
+            (1.0 - lower_gamma_ratio(1.0, 2.0) - (-2.0f64).exp()).abs() < 1e-9
+        );
+        // 19 degrees of freedom, critical value at 5%.
+        assert!(
+            (1.0 - lower_gamma_ratio(9.5, 30.144 / 2.0) - 0.05).abs() < 1e-4
+        );
+        assert_eq!(lower_gamma_ratio(9.5, 0.0), 0.0);
+        assert!(lower_gamma_ratio(9.5, 5000.0) > 0.999_999);
+    }
+
+    #[test]
+    fn test_luck_stats() {
+        let rng = DiceRng::seeded(7);
+        let outcomes: Vec<RollOutcome> = (0..200)
+            .map(|_| Roll::parse("d20 + 1d6").unwrap().resolve(&rng))
+            .collect();
+        let stats = LuckStats::new(&outcomes);
+        assert_eq!(stats.dice().len(), 2);
+        let d20 = stats.die(20).unwrap();
+        assert_eq!(d20.total(), 200);
+        assert_eq!(stats.die(6).unwrap().total(), 200);
+        assert_eq!(stats.nat_20s(), d20.counts()[19]);
+        assert_eq!(stats.nat_1s(), d20.counts()[0]);
+        assert!(d20.enough_rolls());
+        assert!((d20.average() - 10.5).abs() < 1.5);
+        assert!(stats.format().contains("d20 results:"));
+
+        let loaded = FaceCounts {
+            size: 6,
+            counts: vec![0, 0, 0, 0, 0, 60],
+        };
+        assert_eq!(loaded.average(), 6.0);
+        assert_eq!(loaded.chi_squared(), 300.0);
+        assert_eq!(loaded.verdict(), "suspicious");
+        let fair = FaceCounts {
+            size: 6,
+            counts: vec![10; 6],
+        };
+        assert_eq!(fair.chi_squared(), 0.0);
+        assert_eq!(fair.verdict(), "looks fair");
+        assert_eq!(FaceCounts::new(6).verdict(), "not enough rolls");
+    }
+
+    #[test]
+    fn test_export_rolls() {
+        let rng = DiceRng::seeded(3);
+        let mut outcome = Roll::parse("2d6 + 1")
+            .unwrap()
+            .labelled("Sneak, attack")
+            .resolve(&rng);
+        outcome.timestamp = 100;
+        let dice = outcome.format_results();
+        let total = outcome.total();
+
+        let csv = export_rolls(&[outcome.clone()], LogFormat::Csv).unwrap();
+        assert_eq!(
+            csv,
+            format!(
+                "timestamp,label,expression,dice,total\n\
+                 100,\"Sneak, attack\",2d6 + 1,\"{dice}\",{total}\n"
+            )
+        );
+
+        let json = export_rolls(&[outcome], LogFormat::Json).unwrap();
+        let records: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(records[0]["label"], "Sneak, attack");
+        assert_eq!(records[0]["expression"], "2d6 + 1");
+        assert_eq!(records[0]["dice"], dice.as_str());
+        assert_eq!(records[0]["total"], total);
+        assert_eq!(records[0]["timestamp"], 100);
+
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
 }
@@ -3,7 +3,7 @@ use ratatui::{crossterm::event::KeyCode, layout::Constraint};
 use crate::{
     Handler, SheetState, editors,
     els::{self, BORDER},
-    spells, stats, view,
+    roll, spells, stats, view,
 };
 
 pub struct SheetScene {
@@ -16,6 +16,8 @@ impl SheetScene {
         stats::Stat::STATS
             .iter()
             .for_each(|s| layout.add_el(els::StatEl::new(*s)));
+        layout.add_group(els::PerceptionEl);
+        layout.add_group(els::SavesEl);
         layout.add_group(els::SkillsEl);
         layout.add_column();
         layout.add_el(els::TextEl::new("Name", &|s| s.name.clone(), &|s| {
@@ -32,12 +34,44 @@ impl SheetScene {
                 Box::new(editors::IntEditorModal::new(
                     "Level",
                     s.level,
-                    Box::new(|level, state| state.level = level),
+                    Box::new(|level, state| {
+                        state.level = level;
+                        state.rebuild_stats();
+                    }),
                 ))
             },
         ));
+        layout.add_el(els::TextEl::new(
+            "Level Up",
+            &|s| match s.level {
+                ..stats::LevelGrants::MAX_LEVEL => {
+                    format!("Advance to level {}", s.level.max(0) + 1)
+                }
+                _ => "Maximum level".to_string(),
+            },
+            &|s| Box::new(editors::LevelUpModal::new(s)),
+        ));
+        layout.add_el(els::TextEl::new(
+            "Build",
+            &|s| {
+                let build = &s.build;
+                [&build.ancestry, &build.background, &build.class]
+                    .iter()
+                    .map(|source| source.name.as_str())
+                    .filter(|name| !name.is_empty())
+                    .collect::<Vec<&str>>()
+                    .join(" / ")
+            },
+            &|_| Box::new(BuildScene::new()),
+        ));
+        layout.add_group(els::HitPointsEl);
+        layout.add_group(els::DeathEl);
+        layout.add_group(els::ConditionsEl);
+        layout.add_group(els::ArmorEl);
         layout.add_el(els::SpellbookStatus);
         layout.add_group(els::Dice);
+        layout.add_group(els::ModifiersEl);
+        layout.add_group(els::MacrosEl);
         layout.add_group(els::RollHistory::new(10));
         Self { layout }
     }
@@ -49,6 +83,79 @@ impl view::Scene<SheetState> for SheetScene {
     }
 }
 
+/// Ancestry, background, class and free boosts which determine the ability
+/// scores.
+pub struct BuildScene {
+    layout: view::Layout<SheetState>,
+}
+
+impl BuildScene {
+    pub fn new() -> Self {
+        let mut layout = view::Layout::new();
+        layout.add_group(els::BuildTable::new());
+        Self { layout }
+    }
+}
+
+impl view::Scene<SheetState> for BuildScene {
+    fn layout(&self) -> &view::Layout<SheetState> {
+        &self.layout
+    }
+
+    fn handle_key_press(
+        &mut self,
+        key: KeyCode,
+        _state: &mut SheetState,
+    ) -> Handler {
+        match key {
+            KeyCode::Esc => Handler::Close,
+            _ => Handler::Default,
+        }
+    }
+}
+
+/// Statistics on the results of the dice across the roll history.
+pub struct LuckScene {
+    layout: view::Layout<SheetState>,
+}
+
+impl LuckScene {
+    /// Name of the file the statistics are exported to.
+    const EXPORT_FILE: &str = "luck.txt";
+
+    pub fn new() -> Self {
+        let mut layout = view::Layout::new();
+        layout.add_el(els::D20Frequencies);
+        layout.add_column();
+        layout.add_el(els::DiceFairness);
+        Self { layout }
+    }
+
+    /// Write the statistics to a text file in the data directory.
+    fn export(state: &SheetState) -> Handler {
+        let text = roll::LuckStats::new(&state.rolls).format();
+        Handler::Open(Box::new(editors::export(Self::EXPORT_FILE, text)))
+    }
+}
+
+impl view::Scene<SheetState> for LuckScene {
+    fn layout(&self) -> &view::Layout<SheetState> {
+        &self.layout
+    }
+
+    fn handle_key_press(
+        &mut self,
+        key: KeyCode,
+        state: &mut SheetState,
+    ) -> Handler {
+        match key {
+            KeyCode::Char('e') => Self::export(state),
+            KeyCode::Esc => Handler::Close,
+            _ => Handler::Default,
+        }
+    }
+}
+
 pub struct SpellbookScene {
     view: editors::EditorState<editors::SpellbookTablePos>,
     layout: view::Layout<SheetState>,
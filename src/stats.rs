@@ -1,6 +1,10 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-use crate::SheetState;
+use crate::{
+    SheetState,
+    modifiers::{Modifier, ModifierKind, Resolution, Selector, Target},
+    roll,
+};
 
 #[derive(
     Debug,
@@ -75,8 +79,183 @@ impl Default for Stats {
     }
 }
 
+/// A choice made when building a character which grants ability boosts and
+/// flaws, e.g. an ancestry.
 #[derive(
-    Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+    Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct BoostSource {
+    pub name: String,
+    pub boosts: Vec<Stat>,
+    pub flaws: Vec<Stat>,
+}
+
+impl BoostSource {
+    pub fn toggle_boost(&mut self, stat: Stat) {
+        toggle(&mut self.boosts, stat);
+    }
+
+    pub fn toggle_flaw(&mut self, stat: Stat) {
+        toggle(&mut self.flaws, stat);
+    }
+}
+
+/// Add the stat to the list if absent, else remove it.
+fn toggle(stats: &mut Vec<Stat>, stat: Stat) {
+    match stats.iter().position(|&s| s == stat) {
+        Some(i) => {
+            stats.remove(i);
+        }
+        None => stats.push(stat),
+    }
+}
+
+/// The choices made when building a character which determine their ability
+/// scores. Every score starts at 10 and is changed by any manual adjustment,
+/// then the boosts and flaws from ancestry, background and class, then the
+/// free boosts gained at levels 1, 5, 10, 15 and 20.
+#[derive(
+    Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct Build {
+    pub ancestry: BoostSource,
+    pub background: BoostSource,
+    pub class: BoostSource,
+
+    /// Free boosts chosen at each level they are gained.
+    free: BTreeMap<i64, Vec<Stat>>,
+
+    /// Changes to scores entered by hand, e.g. from a sheet made before
+    /// builds were tracked.
+    adjustments: HashMap<Stat, i64>,
+}
+
+impl Build {
+    const BASE_SCORE: i64 = 10;
+
+    /// Levels at which free boosts are gained.
+    pub const FREE_BOOST_LEVELS: &[i64] = &[1, 5, 10, 15, 20];
+
+    /// Number of free boosts gained at each of those levels.
+    pub const FREE_BOOSTS: usize = 4;
+
+    pub fn free_boosts(&self, level: i64) -> &[Stat] {
+        self.free.get(&level).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Replace the free boosts chosen at the provided level.
+    fn set_free_boosts(&mut self, level: i64, boosts: Vec<Stat>) {
+        self.free.insert(level, boosts);
+    }
+
+    /// Add or remove a free boost to a stat at the provided level. Returns
+    /// false if the boost can't be added because all of the boosts at that
+    /// level have been used, or free boosts aren't gained at that level.
+    pub fn toggle_free_boost(&mut self, level: i64, stat: Stat) -> bool {
+        if !Self::FREE_BOOST_LEVELS.contains(&level) {
+            return false;
+        }
+        let boosts = self.free.entry(level).or_default();
+        if !boosts.contains(&stat) && boosts.len() >= Self::FREE_BOOSTS {
+            return false;
+        }
+        toggle(boosts, stat);
+        true
+    }
+
+    pub fn adjustment(&self, stat: Stat) -> i64 {
+        self.adjustments.get(&stat).copied().unwrap_or(0)
+    }
+
+    pub fn set_adjustment(&mut self, stat: Stat, adjustment: i64) {
+        if adjustment == 0 {
+            self.adjustments.remove(&stat);
+        } else {
+            self.adjustments.insert(stat, adjustment);
+        }
+    }
+
+    /// Whether nothing has been chosen for this build.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Calculate the score of a stat at the provided level, with each change
+    /// to it labelled with its source. A boost increases a score by 2, or by
+    /// 1 if it is already 18 or more. A flaw decreases it by 2.
+    pub fn provenance(
+        &self,
+        stat: Stat,
+        level: i64,
+    ) -> (i64, Vec<(String, i64)>) {
+        let mut score = Self::BASE_SCORE;
+        let mut changes = Vec::new();
+        let mut change = |label: String, amount: i64, score: &mut i64| {
+            *score += amount;
+            changes.push((label, amount));
+        };
+
+        let adjustment = self.adjustment(stat);
+        if adjustment != 0 {
+            change("Manual".to_string(), adjustment, &mut score);
+        }
+
+        let sources = [
+            ("Ancestry", &self.ancestry),
+            ("Background", &self.background),
+            ("Class", &self.class),
+        ];
+        for (kind, source) in sources {
+            let label = if source.name.is_empty() {
+                kind.to_string()
+            } else {
+                format!("{kind} ({})", source.name)
+            };
+            if source.flaws.contains(&stat) {
+                change(format!("{label} flaw"), -2, &mut score);
+            }
+            if source.boosts.contains(&stat) {
+                change(label, Self::boost(score), &mut score);
+            }
+        }
+
+        for &boost_level in Self::FREE_BOOST_LEVELS {
+            if boost_level <= level.max(1)
+                && self.free_boosts(boost_level).contains(&stat)
+            {
+                let label = format!("Level {boost_level}");
+                change(label, Self::boost(score), &mut score);
+            }
+        }
+
+        (score, changes)
+    }
+
+    /// Amount a boost increases the provided score by.
+    fn boost(score: i64) -> i64 {
+        if score >= 18 { 1 } else { 2 }
+    }
+
+    /// Calculate every ability score at the provided level.
+    pub fn stats(&self, level: i64) -> Stats {
+        let mut stats = Stats::default();
+        for &stat in Stat::STATS {
+            stats.set_score(stat, self.provenance(stat, level).0);
+        }
+        stats
+    }
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
 )]
 pub enum Proficiency {
     Untrained,
@@ -117,7 +296,7 @@ impl Proficiency {
         }
     }
 
-    fn modifier(&self, level: i64) -> i64 {
+    pub fn modifier(&self, level: i64) -> i64 {
         use Proficiency::*;
         match self {
             Untrained => 0,
@@ -140,6 +319,11 @@ pub struct Skill {
     pub name: String,
     pub stat: Stat,
     pub proficiency: Proficiency,
+
+    /// Whether this is a Lore skill added to the sheet, which can be renamed
+    /// or removed.
+    #[serde(default)]
+    pub lore: bool,
 }
 
 impl Skill {
@@ -148,11 +332,75 @@ impl Skill {
             name: name.to_string(),
             stat,
             proficiency: Proficiency::Untrained,
+            lore: false,
+        }
+    }
+
+    /// Create a Lore skill on the provided topic, e.g. Warfare.
+    fn lore(topic: &str) -> Self {
+        Self {
+            lore: true,
+            ..Self::new(&Self::lore_name(topic), Stat::Intelligence)
+        }
+    }
+
+    /// Name of the Lore skill on a topic, e.g. Warfare Lore. The topic may
+    /// already end in Lore.
+    pub fn lore_name(topic: &str) -> String {
+        let topic = topic.trim();
+        if topic.to_lowercase().ends_with("lore") {
+            topic.to_string()
+        } else {
+            format!("{topic} Lore")
         }
     }
 
     pub fn modifier(&self, sheet: &SheetState) -> i64 {
         sheet.stats.modifier(self.stat) + self.proficiency.modifier(sheet.level)
+            - self.armor_penalty(sheet)
+            + self.modifiers(sheet).total()
+    }
+
+    /// Bonuses and penalties from effects which apply to this skill.
+    fn modifiers(&self, sheet: &SheetState) -> Resolution {
+        let target = if self.name == Perception::NAME {
+            Target::Perception
+        } else {
+            Target::Skill {
+                name: &self.name,
+                stat: self.stat,
+            }
+        };
+        sheet.resolve_modifiers(target)
+    }
+
+    /// Check penalty from the armor worn, which applies to skills using STR
+    /// or DEX.
+    fn armor_penalty(&self, sheet: &SheetState) -> i64 {
+        match self.stat {
+            Stat::Strength | Stat::Dexterity => sheet.ac.armor.check_penalty,
+            _ => 0,
+        }
+    }
+
+    /// Roll for a check using this skill, with the stat and proficiency
+    /// bonuses, any armor check penalty and modifiers from effects itemised.
+    pub fn roll(&self, sheet: &SheetState) -> roll::Roll {
+        let mut roll = roll::Roll::new(1, 20).plus_labelled(
+            sheet.stats.modifier(self.stat) as f64,
+            self.stat.short(),
+        );
+        if self.proficiency != Proficiency::Untrained {
+            roll = roll.plus_labelled(
+                self.proficiency.modifier(sheet.level) as f64,
+                format!("{:?}", self.proficiency),
+            );
+        }
+        let penalty = self.armor_penalty(sheet);
+        if penalty != 0 {
+            roll = roll.plus_labelled(-penalty as f64, "Armor");
+        }
+        self.modifiers(sheet).apply(roll).labelled(&self.name)
     }
 }
 
@@ -167,6 +415,91 @@ impl Skills {
     pub fn lookup_mut(&mut self, name: &str) -> Option<&mut Skill> {
         self.0.iter_mut().find(|s| s.name == name)
     }
+
+    pub fn remove(&mut self, name: &str) -> Option<Skill> {
+        let index = self.0.iter().position(|s| s.name == name)?;
+        Some(self.0.remove(index))
+    }
+
+    /// Check that a Lore skill on the topic can be added, or the skill at
+    /// the provided index renamed to it, without a name clash.
+    fn check_lore(
+        &self,
+        topic: &str,
+        index: Option<usize>,
+    ) -> Result<(), String> {
+        let name = Skill::lore_name(topic);
+        let taken = self.0.iter().enumerate().any(|(i, s)| {
+            Some(i) != index && s.name.eq_ignore_ascii_case(&name)
+        });
+        if topic.trim().is_empty() {
+            Err("Expected a topic.".into())
+        } else if taken {
+            Err(format!("{name} already exists."))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Add a Lore skill on the provided topic. Fails if the topic is empty or
+    /// the skill already exists.
+    pub fn add_lore(&mut self, topic: &str) -> Result<(), String> {
+        self.check_lore(topic, None)?;
+        self.0.push(Skill::lore(topic));
+        Ok(())
+    }
+
+    /// Change the topic of the Lore skill at the provided index. Fails if
+    /// that isn't a Lore skill or the new name is taken.
+    pub fn rename_lore(
+        &mut self,
+        index: usize,
+        topic: &str,
+    ) -> Result<(), String> {
+        self.check_lore(topic, Some(index))?;
+        match self.0.get_mut(index) {
+            Some(skill) if skill.lore => {
+                skill.name = Skill::lore_name(topic);
+                Ok(())
+            }
+            _ => Err("Only Lore skills can be renamed.".into()),
+        }
+    }
+
+    /// Remove the Lore skill at the provided index, if it is one.
+    pub fn remove_lore(&mut self, index: usize) -> Option<Skill> {
+        if self.0.get(index)?.lore {
+            Some(self.0.remove(index))
+        } else {
+            None
+        }
+    }
+}
+
+/// Perception, which works like a skill using WIS but has its own
+/// proficiency track and is the usual roll for initiative.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Perception {
+    pub proficiency: Proficiency,
+}
+
+impl Perception {
+    pub const NAME: &str = "Perception";
+
+    fn skill(&self) -> Skill {
+        Skill {
+            proficiency: self.proficiency,
+            ..Skill::new(Self::NAME, Stat::Wisdom)
+        }
+    }
+
+    pub fn modifier(&self, sheet: &SheetState) -> i64 {
+        self.skill().modifier(sheet)
+    }
+
+    pub fn roll(&self, sheet: &SheetState) -> roll::Roll {
+        self.skill().roll(sheet)
+    }
 }
 
 impl Default for Skills {
@@ -182,7 +515,6 @@ impl Default for Skills {
             Skill::new("Medicine", Stat::Wisdom),
             Skill::new("Nature", Stat::Wisdom),
             Skill::new("Occultism", Stat::Intelligence),
-            Skill::new("Perception", Stat::Wisdom),
             Skill::new("Performance", Stat::Charisma),
             Skill::new("Religion", Stat::Wisdom),
             Skill::new("Society", Stat::Intelligence),
@@ -193,6 +525,582 @@ impl Default for Skills {
     }
 }
 
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum Save {
+    Fortitude,
+    Reflex,
+    Will,
+}
+
+impl Save {
+    pub const SAVES: &[Save] = &[Save::Fortitude, Save::Reflex, Save::Will];
+
+    /// Stat which this save uses.
+    pub fn stat(&self) -> Stat {
+        match self {
+            Save::Fortitude => Stat::Constitution,
+            Save::Reflex => Stat::Dexterity,
+            Save::Will => Stat::Wisdom,
+        }
+    }
+
+    pub fn short(&self) -> &'static str {
+        match self {
+            Save::Fortitude => "Fort",
+            Save::Reflex => "Ref",
+            Save::Will => "Will",
+        }
+    }
+}
+
+/// Proficiency in each saving throw. Saves which haven't been set are
+/// untrained.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Saves(HashMap<Save, Proficiency>);
+
+impl Saves {
+    pub fn proficiency(&self, save: Save) -> Proficiency {
+        self.0.get(&save).copied().unwrap_or(Proficiency::Untrained)
+    }
+
+    pub fn set_proficiency(&mut self, save: Save, proficiency: Proficiency) {
+        self.0.insert(save, proficiency);
+    }
+
+    pub fn modifier(&self, save: Save, sheet: &SheetState) -> i64 {
+        sheet.stats.modifier(save.stat())
+            + self.proficiency(save).modifier(sheet.level)
+            + sheet.resolve_modifiers(Target::Save(save)).total()
+    }
+
+    /// Roll a saving throw, with the stat and proficiency bonuses and
+    /// modifiers from effects itemised.
+    pub fn roll(&self, save: Save, sheet: &SheetState) -> roll::Roll {
+        let stat = save.stat();
+        let mut roll = roll::Roll::new(1, 20)
+            .plus_labelled(sheet.stats.modifier(stat) as f64, stat.short());
+        let proficiency = self.proficiency(save);
+        if proficiency != Proficiency::Untrained {
+            roll = roll.plus_labelled(
+                proficiency.modifier(sheet.level) as f64,
+                format!("{proficiency:?}"),
+            );
+        }
+        sheet
+            .resolve_modifiers(Target::Save(save))
+            .apply(roll)
+            .labelled(format!("{save:?}"))
+    }
+}
+
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum ArmorCategory {
+    #[default]
+    Unarmored,
+    Light,
+    Medium,
+    Heavy,
+}
+
+impl ArmorCategory {
+    /// The next heavier category, wrapping around to unarmored.
+    pub fn next(&self) -> ArmorCategory {
+        use ArmorCategory::*;
+        match self {
+            Unarmored => Light,
+            Light => Medium,
+            Medium => Heavy,
+            Heavy => Unarmored,
+        }
+    }
+}
+
+/// Armor worn by a character.
+#[derive(
+    Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct Armor {
+    pub name: String,
+    pub category: ArmorCategory,
+
+    /// Item bonus to AC.
+    pub item_bonus: i64,
+
+    /// Maximum DEX modifier which can be added to AC. Unarmored has no cap.
+    pub dex_cap: i64,
+
+    /// Penalty to STR and DEX skill checks, as a positive number.
+    pub check_penalty: i64,
+
+    /// Penalty to speed in feet, as a positive number.
+    pub speed_penalty: i64,
+}
+
+impl Armor {
+    pub fn dex_cap(&self) -> Option<i64> {
+        if self.category == ArmorCategory::Unarmored {
+            None
+        } else {
+            Some(self.dex_cap)
+        }
+    }
+}
+
+/// Armor class of a character, from their armor, proficiency in it and a
+/// shield if raised.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct ArmorClass {
+    pub armor: Armor,
+
+    /// Proficiency in each category of armor. Categories which haven't been
+    /// set are untrained.
+    proficiencies: HashMap<ArmorCategory, Proficiency>,
+
+    /// Circumstance bonus to AC from a shield while it is raised.
+    pub shield_bonus: i64,
+    pub shield_raised: bool,
+}
+
+impl ArmorClass {
+    const BASE: i64 = 10;
+
+    pub fn proficiency(&self, category: ArmorCategory) -> Proficiency {
+        self.proficiencies
+            .get(&category)
+            .copied()
+            .unwrap_or(Proficiency::Untrained)
+    }
+
+    pub fn set_proficiency(
+        &mut self,
+        category: ArmorCategory,
+        proficiency: Proficiency,
+    ) {
+        self.proficiencies.insert(category, proficiency);
+    }
+
+    /// Bonuses to AC from the armor worn and shield raised, which stack with
+    /// modifiers from effects by type.
+    fn modifiers(&self) -> Vec<Modifier> {
+        let mut modifiers = vec![Modifier {
+            source: "Item".to_string(),
+            kind: ModifierKind::Item,
+            value: self.armor.item_bonus,
+            selector: Selector::Ac,
+        }];
+        if self.shield_raised {
+            modifiers.push(Modifier {
+                source: "Shield".to_string(),
+                kind: ModifierKind::Circumstance,
+                value: self.shield_bonus,
+                selector: Selector::Ac,
+            });
+        }
+        modifiers
+    }
+
+    /// Each bonus to AC above the base of 10, labelled with its source.
+    pub fn breakdown(&self, sheet: &SheetState) -> Vec<(String, i64)> {
+        let dex = sheet.stats.modifier(Stat::Dexterity);
+        let dex = match self.armor.dex_cap() {
+            Some(cap) => dex.min(cap),
+            None => dex,
+        };
+        let proficiency = self.proficiency(self.armor.category);
+        let mut parts = vec![
+            (Stat::Dexterity.short(), dex),
+            (
+                format!("{proficiency:?}"),
+                proficiency.modifier(sheet.level),
+            ),
+        ];
+        parts.extend(
+            self.resolution(sheet)
+                .applied
+                .into_iter()
+                .map(|m| (m.source, m.value)),
+        );
+        parts.retain(|(_, value)| *value != 0);
+        parts
+    }
+
+    /// Bonuses and penalties to AC which don't apply because a larger one of
+    /// the same type does.
+    pub fn suppressed(&self, sheet: &SheetState) -> Vec<Modifier> {
+        self.resolution(sheet).suppressed
+    }
+
+    fn resolution(&self, sheet: &SheetState) -> Resolution {
+        let mut modifiers = self.modifiers();
+        modifiers.extend(sheet.active_modifiers());
+        Resolution::new(&modifiers, Target::Ac)
+    }
+
+    pub fn value(&self, sheet: &SheetState) -> i64 {
+        Self::BASE + self.breakdown(sheet).iter().map(|(_, v)| v).sum::<i64>()
+    }
+}
+
+/// Hit points of a character. Maximum HP is derived from ancestry, class and
+/// CON unless overridden, and damage taken is tracked so that increases to
+/// the maximum also apply to current HP.
+#[derive(
+    Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct HitPoints {
+    /// HP granted by ancestry.
+    pub ancestry: i64,
+
+    /// HP granted by class at each level, before adding CON.
+    pub class: i64,
+
+    /// Maximum HP entered manually, replacing the derived value.
+    pub max_override: Option<i64>,
+
+    /// Damage taken, reducing current HP below the maximum.
+    damage: i64,
+
+    /// Temporary HP, which is lost before current HP.
+    pub temp: i64,
+}
+
+impl HitPoints {
+    /// Maximum HP derived from ancestry, class and CON.
+    pub fn derived_max(&self, sheet: &SheetState) -> i64 {
+        let con = sheet.stats.modifier(Stat::Constitution);
+        self.derived_max_at(sheet.level, con)
+    }
+
+    /// Maximum HP derived at the provided level and CON modifier.
+    pub fn derived_max_at(&self, level: i64, con: i64) -> i64 {
+        self.ancestry + (self.class + con) * level.max(1)
+    }
+
+    pub fn max(&self, sheet: &SheetState) -> i64 {
+        self.max_override.unwrap_or_else(|| self.derived_max(sheet))
+    }
+
+    pub fn current(&self, sheet: &SheetState) -> i64 {
+        (self.max(sheet) - self.damage).max(0)
+    }
+
+    /// Take damage, which is absorbed by temporary HP first. Current HP
+    /// doesn't drop below 0.
+    pub fn take_damage(&mut self, amount: i64, sheet: &SheetState) {
+        let amount = amount.max(0);
+        let absorbed = amount.clamp(0, self.temp);
+        self.temp -= absorbed;
+        self.damage = (self.damage + amount - absorbed).min(self.max(sheet));
+    }
+
+    /// Recover HP, up to the maximum.
+    pub fn heal(&mut self, amount: i64) {
+        self.damage = (self.damage - amount.max(0)).max(0);
+    }
+
+    /// Gain temporary HP. These don't stack, so the higher amount is kept.
+    pub fn gain_temp(&mut self, amount: i64) {
+        self.temp = self.temp.max(amount);
+    }
+}
+
+/// Conditions tracking how close a character is to death.
+#[derive(
+    Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct DeathConditions {
+    pub dying: i64,
+    pub wounded: i64,
+    pub doomed: i64,
+}
+
+impl DeathConditions {
+    /// Value of dying at which the character dies.
+    pub fn death_threshold(&self) -> i64 {
+        4 - self.doomed
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.dying >= self.death_threshold()
+    }
+
+    /// Increase dying after taking damage at 0 HP. Dying starts at 1 plus
+    /// wounded, or 2 plus wounded from a critical hit, and increases by the
+    /// same amount if already dying.
+    pub fn knocked_out(&mut self, critical: bool) {
+        let amount = if critical { 2 } else { 1 };
+        if self.dying > 0 {
+            self.dying += amount;
+        } else {
+            self.dying = amount + self.wounded;
+        }
+    }
+
+    /// Stop dying, increasing wounded if the character was dying.
+    pub fn revive(&mut self) {
+        if self.dying > 0 {
+            self.dying = 0;
+            self.wounded += 1;
+        }
+    }
+
+    /// Flat check made at the start of each turn while dying.
+    pub fn recovery_check(&self) -> roll::Roll {
+        roll::Roll::new(1, 20)
+            .against(10 + self.dying)
+            .labelled("Recovery check")
+    }
+
+    /// Apply the result of a recovery check. Success reduces dying by 1, and
+    /// failure increases it by 1, or 2 on a critical.
+    pub fn recover(&mut self, degree: roll::Degree) {
+        if self.dying == 0 {
+            return;
+        }
+
+        self.dying += match degree {
+            roll::Degree::CriticalSuccess => -2,
+            roll::Degree::Success => -1,
+            roll::Degree::Failure => 1,
+            roll::Degree::CriticalFailure => 2,
+        };
+        if self.dying <= 0 {
+            self.dying = 1;
+            self.revive();
+        }
+    }
+}
+
+/// Highest proficiency rank which can be reached at the provided level.
+pub fn proficiency_cap(level: i64) -> Proficiency {
+    match level {
+        ..7 => Proficiency::Expert,
+        7..15 => Proficiency::Master,
+        _ => Proficiency::Legendary,
+    }
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+pub enum FeatKind {
+    Ancestry,
+    Class,
+    General,
+    Skill,
+}
+
+/// A feat taken by the character, in the slot gained at a level.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Feat {
+    pub level: i64,
+    pub kind: FeatKind,
+    pub name: String,
+}
+
+/// What a character gains on reaching a level, other than the HP which
+/// comes from their class.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LevelGrants {
+    pub skill_increases: usize,
+    pub ability_boosts: usize,
+    pub feats: Vec<FeatKind>,
+}
+
+impl LevelGrants {
+    pub const MAX_LEVEL: i64 = 20;
+
+    pub fn at(level: i64) -> Self {
+        let mut feats = Vec::new();
+        if level % 4 == 1 {
+            feats.push(FeatKind::Ancestry);
+        }
+        if level == 1 || level % 2 == 0 {
+            feats.push(FeatKind::Class);
+        }
+        if level % 2 == 0 {
+            feats.push(FeatKind::Skill);
+        }
+        if level % 4 == 3 {
+            feats.push(FeatKind::General);
+        }
+
+        Self {
+            skill_increases: if level >= 3 && level % 2 == 1 { 1 } else { 0 },
+            ability_boosts: if Build::FREE_BOOST_LEVELS.contains(&level) {
+                Build::FREE_BOOSTS
+            } else {
+                0
+            },
+            feats,
+        }
+    }
+}
+
+/// Choices made when advancing a character to their next level, which are
+/// validated and then applied to the sheet together.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LevelUp {
+    /// Level being reached.
+    pub level: i64,
+
+    /// Names of the skills whose proficiency is increased.
+    skill_increases: Vec<String>,
+
+    /// Stats boosted by the free boosts gained at this level.
+    boosts: Vec<Stat>,
+
+    /// Feats taken in the slots gained at this level.
+    pub feats: Vec<Feat>,
+}
+
+impl LevelUp {
+    pub fn new(sheet: &SheetState) -> Self {
+        let level = sheet.level.max(0) + 1;
+        let feats = LevelGrants::at(level)
+            .feats
+            .into_iter()
+            .map(|kind| Feat {
+                level,
+                kind,
+                name: String::new(),
+            })
+            .collect();
+        Self {
+            level,
+            skill_increases: Vec::new(),
+            boosts: sheet.build.free_boosts(level).to_vec(),
+            feats,
+        }
+    }
+
+    pub fn grants(&self) -> LevelGrants {
+        LevelGrants::at(self.level)
+    }
+
+    pub fn increases(&self, skill: &str) -> bool {
+        self.skill_increases.iter().any(|s| s == skill)
+    }
+
+    pub fn toggle_skill_increase(&mut self, skill: &str) {
+        match self.skill_increases.iter().position(|s| s == skill) {
+            Some(i) => {
+                self.skill_increases.remove(i);
+            }
+            None => self.skill_increases.push(skill.to_string()),
+        }
+    }
+
+    pub fn boosts(&self, stat: Stat) -> bool {
+        self.boosts.contains(&stat)
+    }
+
+    pub fn toggle_boost(&mut self, stat: Stat) {
+        toggle(&mut self.boosts, stat);
+    }
+
+    /// Build with the boosts chosen at this level.
+    pub fn build(&self, sheet: &SheetState) -> Build {
+        let mut build = sheet.build.clone();
+        if self.grants().ability_boosts > 0 {
+            build.set_free_boosts(self.level, self.boosts.clone());
+        }
+        build
+    }
+
+    /// Maximum HP before and after levelling up.
+    pub fn hp(&self, sheet: &SheetState) -> (i64, i64) {
+        let stats = self.build(sheet).stats(self.level);
+        let after = match sheet.hp.max_override {
+            Some(max) => max,
+            None => sheet
+                .hp
+                .derived_max_at(self.level, stats.modifier(Stat::Constitution)),
+        };
+        (sheet.hp.max(sheet), after)
+    }
+
+    /// Check that the choices use exactly what this level grants, and that
+    /// no proficiency exceeds the cap for the level.
+    pub fn validate(&self, sheet: &SheetState) -> Result<(), String> {
+        if self.level > LevelGrants::MAX_LEVEL {
+            return Err(format!(
+                "Already at the maximum level, {}.",
+                LevelGrants::MAX_LEVEL
+            ));
+        }
+
+        let grants = self.grants();
+        if self.skill_increases.len() != grants.skill_increases {
+            return Err(format!(
+                "Choose {} skill increase(s), {} chosen.",
+                grants.skill_increases,
+                self.skill_increases.len()
+            ));
+        }
+        let cap = proficiency_cap(self.level);
+        for name in &self.skill_increases {
+            let skill = sheet
+                .skills
+                .lookup(name)
+                .ok_or_else(|| format!("Unknown skill {name}."))?;
+            let increased = skill.proficiency.increase();
+            if increased == skill.proficiency || increased > cap {
+                return Err(format!(
+                    "{name} can't be increased past {cap:?} at level {}.",
+                    self.level
+                ));
+            }
+        }
+
+        if self.boosts.len() != grants.ability_boosts {
+            return Err(format!(
+                "Choose {} ability boosts, {} chosen.",
+                grants.ability_boosts,
+                self.boosts.len()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Apply every choice to the sheet, or nothing if they aren't valid.
+    pub fn apply(&self, sheet: &mut SheetState) -> Result<(), String> {
+        self.validate(sheet)?;
+
+        sheet.level = self.level;
+        for name in &self.skill_increases {
+            if let Some(skill) = sheet.skills.lookup_mut(name) {
+                skill.proficiency = skill.proficiency.increase();
+            }
+        }
+        sheet.build = self.build(sheet);
+        sheet.feats.retain(|feat| feat.level != self.level);
+        sheet.feats.extend(self.feats.iter().cloned());
+        sheet.rebuild_stats();
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -224,4 +1132,322 @@ mod test {
             assert_eq!(Stat::modifier(score), expected);
         }
     }
+
+    #[test]
+    fn test_hit_points() {
+        let mut sheet = SheetState {
+            level: 3,
+            ..Default::default()
+        };
+        sheet.stats.set_score(Stat::Constitution, 14);
+        sheet.hp.ancestry = 8;
+        sheet.hp.class = 10;
+        assert_eq!(sheet.hp.max(&sheet), 8 + (10 + 2) * 3);
+        assert_eq!(sheet.hp.current(&sheet), 44);
+
+        let mut hp = sheet.hp.clone();
+        hp.gain_temp(5);
+        hp.gain_temp(3);
+        assert_eq!(hp.temp, 5);
+        hp.take_damage(8, &sheet);
+        assert_eq!(hp.temp, 0);
+        assert_eq!(hp.current(&sheet), 41);
+        hp.take_damage(100, &sheet);
+        assert_eq!(hp.current(&sheet), 0);
+        hp.heal(10);
+        assert_eq!(hp.current(&sheet), 10);
+        hp.heal(100);
+        assert_eq!(hp.current(&sheet), 44);
+        hp.take_damage(-5, &sheet);
+        assert_eq!(hp.current(&sheet), 44);
+
+        hp.max_override = Some(30);
+        assert_eq!(hp.max(&sheet), 30);
+        assert_eq!(hp.derived_max(&sheet), 44);
+    }
+
+    #[test]
+    fn test_death_conditions() {
+        let mut death = DeathConditions {
+            wounded: 1,
+            ..Default::default()
+        };
+        death.knocked_out(false);
+        assert_eq!(death.dying, 2);
+        death.recover(roll::Degree::Failure);
+        assert_eq!(death.dying, 3);
+        assert!(!death.is_dead());
+        death.recover(roll::Degree::CriticalSuccess);
+        assert_eq!(death.dying, 1);
+        death.recover(roll::Degree::Success);
+        assert_eq!(death.dying, 0);
+        assert_eq!(death.wounded, 2);
+
+        death.knocked_out(true);
+        assert_eq!(death.dying, 4);
+        assert!(death.is_dead());
+
+        let mut doomed = DeathConditions {
+            doomed: 2,
+            ..Default::default()
+        };
+        doomed.knocked_out(true);
+        assert_eq!(doomed.death_threshold(), 2);
+        assert!(doomed.is_dead());
+        assert_eq!(doomed.recovery_check().dc(), Some(12));
+    }
+
+    #[test]
+    fn test_saves() {
+        let mut sheet = SheetState {
+            level: 2,
+            ..Default::default()
+        };
+        sheet.stats.set_score(Stat::Dexterity, 16);
+        sheet
+            .saves
+            .set_proficiency(Save::Reflex, Proficiency::Expert);
+        assert_eq!(sheet.saves.modifier(Save::Reflex, &sheet), 3 + 4 + 2);
+        assert_eq!(sheet.saves.modifier(Save::Will, &sheet), 0);
+        assert_eq!(
+            sheet.saves.roll(Save::Reflex, &sheet).format_breakdown(),
+            "DEX +3, Expert +6"
+        );
+
+        let json = serde_json::to_string(&sheet.saves).unwrap();
+        let saves: Saves = serde_json::from_str(&json).unwrap();
+        assert_eq!(saves.proficiency(Save::Reflex), Proficiency::Expert);
+        assert_eq!(saves.proficiency(Save::Fortitude), Proficiency::Untrained);
+    }
+
+    #[test]
+    fn test_armor_class() {
+        let mut sheet = SheetState {
+            level: 1,
+            ..Default::default()
+        };
+        sheet.stats.set_score(Stat::Dexterity, 18);
+        assert_eq!(sheet.ac.value(&sheet), 14);
+
+        sheet.ac.armor = Armor {
+            name: "Chain mail".to_string(),
+            category: ArmorCategory::Medium,
+            item_bonus: 4,
+            dex_cap: 1,
+            check_penalty: 2,
+            speed_penalty: 5,
+        };
+        sheet
+            .ac
+            .set_proficiency(ArmorCategory::Medium, Proficiency::Trained);
+        assert_eq!(sheet.ac.value(&sheet), 10 + 1 + 3 + 4);
+        sheet.ac.shield_bonus = 2;
+        sheet.ac.shield_raised = true;
+        assert_eq!(sheet.ac.value(&sheet), 20);
+
+        let stealth = sheet.skills.lookup("Stealth").unwrap();
+        assert_eq!(stealth.modifier(&sheet), 4 - 2);
+        assert_eq!(stealth.roll(&sheet).format_breakdown(), "DEX +4, Armor -2");
+        let arcana = sheet.skills.lookup("Arcana").unwrap();
+        assert_eq!(arcana.modifier(&sheet), 0);
+    }
+
+    #[test]
+    fn test_effect_modifiers() {
+        let mut sheet = SheetState {
+            level: 1,
+            ..Default::default()
+        };
+        sheet.ac.shield_bonus = 2;
+        sheet.ac.shield_raised = true;
+        sheet.modifiers =
+            ["Cover: +4 circumstance AC", "Heroism: +1 status all"]
+                .iter()
+                .map(|text| Modifier::parse(text).unwrap())
+                .collect();
+        assert_eq!(sheet.ac.value(&sheet), 10 + 4 + 1);
+        assert_eq!(
+            sheet.ac.breakdown(&sheet),
+            [("Cover".to_string(), 4), ("Heroism".to_string(), 1)]
+        );
+        let suppressed = sheet.ac.suppressed(&sheet);
+        assert_eq!(suppressed.len(), 1);
+        assert_eq!(suppressed[0].source, "Shield");
+
+        sheet
+            .modifiers
+            .push(Modifier::parse("Inspire: +2 status skills").unwrap());
+        let stealth = sheet.skills.lookup("Stealth").unwrap();
+        assert_eq!(stealth.modifier(&sheet), 2);
+        let roll = stealth.roll(&sheet);
+        assert_eq!(roll.format_breakdown(), "DEX +0, Inspire +2");
+        assert_eq!(
+            roll.format_suppressed(),
+            "Suppressed: Heroism: +1 status all"
+        );
+        assert_eq!(sheet.perception.modifier(&sheet), 1);
+        assert_eq!(sheet.saves.modifier(Save::Will, &sheet), 1);
+    }
+
+    #[test]
+    fn test_perception() {
+        let mut sheet = SheetState {
+            level: 1,
+            ..Default::default()
+        };
+        assert!(sheet.skills.lookup(Perception::NAME).is_none());
+        sheet.stats.set_score(Stat::Wisdom, 14);
+        sheet.perception.proficiency = Proficiency::Trained;
+        assert_eq!(sheet.perception.modifier(&sheet), 2 + 3);
+        assert_eq!(
+            sheet.perception.roll(&sheet).format_breakdown(),
+            "WIS +2, Trained +3"
+        );
+        assert_eq!(sheet.variable("perception"), Some(5));
+        assert_eq!(sheet.variable("skill.Perception"), Some(5));
+    }
+
+    #[test]
+    fn test_lore_skills() {
+        let mut skills = Skills::default();
+        let count = skills.0.len();
+        assert!(skills.add_lore("Warfare").is_ok());
+        assert!(skills.add_lore("Tanning Lore").is_ok());
+        assert!(skills.add_lore("warfare lore").is_err());
+        assert!(skills.add_lore("Warfare").is_err());
+        assert!(skills.add_lore("  ").is_err());
+
+        let warfare = skills.lookup("Warfare Lore").unwrap();
+        assert_eq!(warfare.stat, Stat::Intelligence);
+        assert!(warfare.lore);
+        assert!(skills.lookup("Tanning Lore").is_some());
+
+        assert!(skills.rename_lore(0, "Acrobatics").is_err());
+        assert!(skills.rename_lore(count, "Tanning").is_err());
+        assert!(skills.rename_lore(count, "warfare lore").is_ok());
+        assert_eq!(skills.0[count].name, "warfare lore");
+        assert!(skills.rename_lore(count, "Engineering").is_ok());
+        assert!(skills.lookup("Engineering Lore").is_some());
+        assert!(skills.lookup("Warfare Lore").is_none());
+
+        let json = serde_json::to_string(&skills).unwrap();
+        let mut loaded: Skills = serde_json::from_str(&json).unwrap();
+        assert!(loaded.lookup("Engineering Lore").unwrap().lore);
+        assert!(!loaded.lookup("Arcana").unwrap().lore);
+
+        assert!(loaded.remove_lore(0).is_none());
+        let removed = loaded.remove_lore(count).unwrap();
+        assert_eq!(removed.name, "Engineering Lore");
+        assert!(loaded.lookup("Engineering Lore").is_none());
+    }
+
+    #[test]
+    fn test_build() {
+        let mut build = Build {
+            ancestry: BoostSource {
+                name: "Elf".to_string(),
+                boosts: vec![Stat::Dexterity, Stat::Intelligence],
+                flaws: vec![Stat::Constitution],
+            },
+            ..Default::default()
+        };
+        build.background.toggle_boost(Stat::Dexterity);
+        build.class.toggle_boost(Stat::Dexterity);
+        for stat in [
+            Stat::Dexterity,
+            Stat::Intelligence,
+            Stat::Wisdom,
+            Stat::Constitution,
+        ] {
+            assert!(build.toggle_free_boost(1, stat));
+        }
+        assert!(!build.toggle_free_boost(1, Stat::Charisma));
+        assert!(!build.toggle_free_boost(2, Stat::Charisma));
+        assert!(build.toggle_free_boost(5, Stat::Dexterity));
+
+        let stats = build.stats(1);
+        assert_eq!(stats.score(Stat::Dexterity), 18);
+        assert_eq!(stats.score(Stat::Intelligence), 14);
+        assert_eq!(stats.score(Stat::Constitution), 10);
+        assert_eq!(stats.score(Stat::Charisma), 10);
+
+        // Boosts at 18 or above only add 1.
+        assert_eq!(build.stats(5).score(Stat::Dexterity), 19);
+
+        build.set_adjustment(Stat::Strength, 4);
+        let (score, changes) = build.provenance(Stat::Constitution, 1);
+        assert_eq!(score, 10);
+        assert_eq!(
+            changes,
+            vec![
+                ("Ancestry (Elf) flaw".to_string(), -2),
+                ("Level 1".to_string(), 2)
+            ]
+        );
+        assert_eq!(build.stats(1).score(Stat::Strength), 14);
+
+        build.toggle_free_boost(1, Stat::Wisdom);
+        assert_eq!(build.free_boosts(1).len(), 3);
+        assert!(build.toggle_free_boost(1, Stat::Charisma));
+    }
+
+    #[test]
+    fn test_level_grants() {
+        let first = LevelGrants::at(1);
+        assert_eq!(first.ability_boosts, 4);
+        assert_eq!(first.skill_increases, 0);
+        assert_eq!(first.feats, vec![FeatKind::Ancestry, FeatKind::Class]);
+        assert_eq!(
+            LevelGrants::at(2).feats,
+            vec![FeatKind::Class, FeatKind::Skill]
+        );
+        let third = LevelGrants::at(3);
+        assert_eq!(third.skill_increases, 1);
+        assert_eq!(third.feats, vec![FeatKind::General]);
+        assert_eq!(LevelGrants::at(5).ability_boosts, 4);
+
+        assert_eq!(proficiency_cap(6), Proficiency::Expert);
+        assert_eq!(proficiency_cap(7), Proficiency::Master);
+        assert_eq!(proficiency_cap(15), Proficiency::Legendary);
+    }
+
+    #[test]
+    fn test_level_up() {
+        let mut sheet = SheetState {
+            level: 4,
+            ..Default::default()
+        };
+        sheet.hp.class = 8;
+        sheet.skills.lookup_mut("Stealth").unwrap().proficiency =
+            Proficiency::Expert;
+
+        let mut level_up = LevelUp::new(&sheet);
+        assert_eq!(level_up.level, 5);
+        assert!(level_up.validate(&sheet).is_err());
+
+        // Master is above the cap at level 5.
+        level_up.toggle_skill_increase("Stealth");
+        for &stat in &Stat::STATS[..4] {
+            level_up.toggle_boost(stat);
+        }
+        assert!(level_up.apply(&mut sheet).is_err());
+        assert_eq!(sheet.level, 4);
+        assert_eq!(sheet.stats.score(Stat::Constitution), 10);
+
+        level_up.toggle_skill_increase("Stealth");
+        level_up.toggle_skill_increase("Arcana");
+        assert_eq!(level_up.hp(&sheet), (8 * 4, 9 * 5));
+        level_up.feats[0].name = "Elf Step".to_string();
+        level_up.apply(&mut sheet).unwrap();
+
+        assert_eq!(sheet.level, 5);
+        assert_eq!(sheet.stats.score(Stat::Constitution), 12);
+        assert_eq!(
+            sheet.skills.lookup("Arcana").unwrap().proficiency,
+            Proficiency::Trained
+        );
+        assert_eq!(sheet.hp.max(&sheet), 45);
+        assert_eq!(sheet.feats[0].name, "Elf Step");
+        assert_eq!(sheet.feats[0].kind, FeatKind::Ancestry);
+    }
 }
@@ -136,8 +136,8 @@ pub trait ElSimp<S> {
         HandleResult::Default
     }
 
-    /// Handle a key press on this element. By default, delegates to select or
-    /// roll method implementations.
+    /// Handle a key press on this element. By default, delegates to select,
+    /// roll, check or odds method implementations.
     fn handle_key_press(
         &self,
         code: KeyCode,
@@ -146,6 +146,8 @@ pub trait ElSimp<S> {
         match code {
             KeyCode::Enter => self.handle_select(state),
             KeyCode::Char('r') => self.handle_roll(state),
+            KeyCode::Char('c') => self.handle_check(state),
+            KeyCode::Char('o') => self.handle_odds(state),
             _ => HandleResult::Default,
         }
     }
@@ -155,6 +157,16 @@ pub trait ElSimp<S> {
         HandleResult::Default
     }
 
+    /// Handle user requesting a check against a DC from this element.
+    fn handle_check(&self, _state: &S) -> HandleResult<S> {
+        HandleResult::Default
+    }
+
+    /// Handle user requesting the odds of this element's roll.
+    fn handle_odds(&self, _state: &S) -> HandleResult<S> {
+        HandleResult::Default
+    }
+
     /// Handle this element being selected.
     fn handle_select(&self, _state: &S) -> HandleResult<S> {
         HandleResult::Default
@@ -214,10 +226,23 @@ pub trait ElGroup<S> {
         code: KeyCode,
         state: &mut S,
         selected: usize,
+    ) -> HandleResult<S> {
+        self.handle_default_key_press(code, state, selected)
+    }
+
+    /// Map the keys shared by all groups to their handlers. Groups which
+    /// override handle_key_press can forward keys they don't handle here.
+    fn handle_default_key_press(
+        &self,
+        code: KeyCode,
+        state: &mut S,
+        selected: usize,
     ) -> HandleResult<S> {
         match code {
             KeyCode::Enter => self.handle_select(state, selected),
             KeyCode::Char('r') => self.handle_roll(state, selected),
+            KeyCode::Char('c') => self.handle_check(state, selected),
+            KeyCode::Char('o') => self.handle_odds(state, selected),
             _ => HandleResult::Default,
         }
     }
@@ -226,6 +251,18 @@ pub trait ElGroup<S> {
         HandleResult::Default
     }
 
+    /// Handle user requesting a check against a DC from a child of this
+    /// element.
+    fn handle_check(&self, _state: &S, _selected: usize) -> HandleResult<S> {
+        HandleResult::Default
+    }
+
+    /// Handle user requesting the odds of the roll of a child of this
+    /// element.
+    fn handle_odds(&self, _state: &S, _selected: usize) -> HandleResult<S> {
+        HandleResult::Default
+    }
+
     /// Handle a child of this element being selected by the user.
     fn handle_select(&self, _state: &S, _selected: usize) -> HandleResult<S> {
         HandleResult::Default
@@ -1029,7 +1066,9 @@ mod test {
         // Add rolls to the state for the roll history element to display.
         let mut state = crate::SheetState::default();
         (0..7).for_each(|_| {
-            state.rolls.push(crate::roll::Roll::new(1, 1).resolve())
+            state
+                .rolls
+                .push(crate::roll::Roll::new(1, 1).resolve(&state.dice))
         });
 
         let mut at = ElPos::default();